@@ -0,0 +1,495 @@
+//! Replays a log of forwarded HTLCs through a `ResourceManager` to evaluate what decisions local
+//! resource conservation would have made for them.
+//!
+//! The log is a chronological CSV file with a header row and the following columns:
+//!
+//! ```text
+//! event,timestamp,incoming_channel,incoming_index,outgoing_channel,outgoing_index,incoming_amount_msat,outgoing_amount_msat,cltv_expiry_delta,endorsed,success,block_height
+//! ```
+//!
+//! Logs with a `.json` or `.jsonl` extension are instead read as JSON lines, with one object per event
+//! that is keyed by the column names above, which requires the `serde` feature. For example:
+//!
+//! ```text
+//! {"event": "add", "timestamp": 1700000000, "incoming_channel": "800000x1x0", "incoming_index": 0, "outgoing_channel": 2, "incoming_amount_msat": 11000, "outgoing_amount_msat": 10000, "cltv_expiry_delta": 40, "endorsed": true}
+//! ```
+//!
+//! `event` is either `add` or `resolve` and `timestamp` is in (possibly fractional) seconds since the
+//! unix epoch. Adds use the amount, expiry and `endorsed` columns (`true`, `false` or empty if the TLV
+//! was absent), resolutions use `outgoing_index` and `success`. Columns that don't apply to an event
+//! may be left empty. Channels may be given as a number or in `800000x1x0` format. Every channel seen in
+//! the log is registered with the limits given on the command line.
+//!
+//! The `block_height` column is optional, and may be left out entirely. When it is set, the manager is
+//! updated to that height before the event is replayed, so that the risk of in-flight HTLCs is evaluated
+//! by the blocks remaining until they expire. Events without a height keep the last height seen, and logs
+//! without any heights are replayed at height zero, so every in-flight HTLC is treated as having its full
+//! expiry delta remaining. As the log doesn't record absolute expiry heights, each HTLC is taken to expire
+//! `cltv_expiry_delta` blocks after the height at which it was added.
+
+#![allow(clippy::needless_return)]
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rust_lrc::resources::clock::MockClock;
+use rust_lrc::resources::interface::{ChannelInfo, Endorsement, ForwardOutcome, LocalResourceManager, ProposedHTLC, ResolvedHTLC};
+use rust_lrc::resources::resource_manager::{ManagerConfig, ResourceManager};
+use rust_lrc::resources::types::{MilliSatoshi, ShortChannelId};
+
+const USAGE: &str = "usage: lrc-sim [--htlc-limit <slots>] [--liquidity-limit <msat>] [--protected-percentage <percent>] <log.csv|log.jsonl>";
+
+const COLUMNS: [&str; 12] = ["event", "timestamp", "incoming_channel", "incoming_index", "outgoing_channel", "outgoing_index",
+	"incoming_amount_msat", "outgoing_amount_msat", "cltv_expiry_delta", "endorsed", "success", "block_height"];
+
+const NUM_COLUMNS: usize = COLUMNS.len();
+
+/// A single entry in the forwarding log.
+#[derive(Debug, PartialEq)]
+enum LogEvent {
+	Add {
+		timestamp: SystemTime,
+		incoming_channel: ShortChannelId,
+		incoming_index: u32,
+		outgoing_channel: ShortChannelId,
+		incoming_amount_msat: MilliSatoshi,
+		outgoing_amount_msat: MilliSatoshi,
+		cltv_expiry_delta: u32,
+		endorsed: Endorsement,
+		block_height: Option<u32>,
+	},
+	Resolve {
+		timestamp: SystemTime,
+		incoming_channel: ShortChannelId,
+		incoming_index: u32,
+		outgoing_channel: ShortChannelId,
+		outgoing_index: u32,
+		success: bool,
+		block_height: Option<u32>,
+	},
+}
+
+impl LogEvent {
+	fn timestamp(&self) -> SystemTime {
+		match self {
+			LogEvent::Add { timestamp, .. } => return *timestamp,
+			LogEvent::Resolve { timestamp, .. } => return *timestamp,
+		}
+	}
+
+	fn block_height(&self) -> Option<u32> {
+		match self {
+			LogEvent::Add { block_height, .. } => return *block_height,
+			LogEvent::Resolve { block_height, .. } => return *block_height,
+		}
+	}
+}
+
+struct SimConfig {
+	htlc_limit: u64,
+	liquidity_limit: MilliSatoshi,
+	protected_percentage: u64,
+	log_path: String,
+}
+
+/// Counts the decisions made over the course of a simulation.
+#[derive(Default)]
+struct Summary {
+	endorsed: u64,
+	unendorsed: u64,
+	no_resources: u64,
+	forward_errors: u64,
+	resolved: u64,
+	resolve_errors: u64,
+}
+
+fn parse_number<T: std::str::FromStr>(field: &str, name: &str) -> Result<T, String> {
+	return field.trim().parse::<T>().map_err(|_| format!("invalid {}: {:?}", name, field));
+}
+
+fn parse_short_channel_id(field: &str, name: &str) -> Result<ShortChannelId, String> {
+	if let Ok(short_channel_id) = field.trim().parse::<ShortChannelId>() {
+		return Ok(short_channel_id);
+	}
+	return Ok(ShortChannelId(parse_number(field, name)?));
+}
+
+fn parse_timestamp(field: &str) -> Result<SystemTime, String> {
+	let seconds: f64 = parse_number(field, "timestamp")?;
+	if !seconds.is_finite() || seconds < 0.0 {
+		return Err(format!("invalid timestamp: {:?}", field));
+	}
+	return Ok(SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds));
+}
+
+fn parse_endorsement(field: &str) -> Result<Endorsement, String> {
+	match field.trim() {
+		"" => return Ok(Endorsement::EndorsementNone),
+		"0" | "false" => return Ok(Endorsement::EndorsementFalse),
+		"1" | "true" => return Ok(Endorsement::EndorsementTrue),
+		_ => return Err(format!("invalid endorsed: {:?}", field)),
+	}
+}
+
+fn parse_bool(field: &str, name: &str) -> Result<bool, String> {
+	match field.trim() {
+		"0" | "false" => return Ok(false),
+		"1" | "true" => return Ok(true),
+		_ => return Err(format!("invalid {}: {:?}", name, field)),
+	}
+}
+
+fn parse_line(line: &str) -> Result<LogEvent, String> {
+	let fields: Vec<&str> = line.split(',').collect();
+	// The trailing block height column may be left out.
+	if fields.len() != NUM_COLUMNS && fields.len() != NUM_COLUMNS - 1 {
+		return Err(format!("expected {} columns, found {}", NUM_COLUMNS, fields.len()));
+	}
+
+	return parse_fields(&fields);
+}
+
+/// Parses a JSON object keyed by column name by converting each value to its CSV representation. Missing and
+/// null values are treated as empty columns.
+#[cfg(feature = "serde")]
+fn parse_json_line(line: &str) -> Result<LogEvent, String> {
+	let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)
+		.map_err(|e| format!("invalid json: {}", e))?;
+
+	if let Some(key) = object.keys().find(|key| !COLUMNS.contains(&key.as_str())) {
+		return Err(format!("unknown field: {:?}", key));
+	}
+
+	let mut values: Vec<String> = Vec::with_capacity(NUM_COLUMNS);
+	for column in COLUMNS {
+		let value = match object.get(column) {
+			None | Some(serde_json::Value::Null) => String::new(),
+			Some(serde_json::Value::String(value)) => value.clone(),
+			Some(serde_json::Value::Number(value)) => json_number(value),
+			Some(serde_json::Value::Bool(value)) => value.to_string(),
+			Some(value) => return Err(format!("invalid {}: {}", column, value)),
+		};
+		values.push(value);
+	}
+
+	return parse_fields(&values.iter().map(|value| value.as_str()).collect::<Vec<&str>>());
+}
+
+/// Returns the CSV representation of a JSON number. Whole numbers are written without a fraction, even if they
+/// were written as a float such as 1.1e4 or 11000.0, so that they can be used for integer columns.
+#[cfg(feature = "serde")]
+fn json_number(value: &serde_json::Number) -> String {
+	if let Some(value) = value.as_u64() {
+		return value.to_string();
+	}
+
+	match value.as_f64() {
+		Some(float) if float.fract() == 0.0 && float >= 0.0 && float < u64::MAX as f64 => return (float as u64).to_string(),
+		Some(float) => return float.to_string(),
+		None => return value.to_string(),
+	}
+}
+
+/// JSON logs are rejected by `parse_args` when the serde feature is disabled, so this is never reached.
+#[cfg(not(feature = "serde"))]
+fn parse_json_line(_line: &str) -> Result<LogEvent, String> {
+	return Err(String::from("json logs require the serde feature"));
+}
+
+/// Returns whether the log at the path provided should be read as JSON lines rather than CSV.
+fn is_json_log(log_path: &str) -> bool {
+	return log_path.ends_with(".json") || log_path.ends_with(".jsonl");
+}
+
+/// Parses an event from its columns, with the trailing block height column optional.
+fn parse_fields(fields: &[&str]) -> Result<LogEvent, String> {
+	let timestamp = parse_timestamp(fields[1])?;
+	let incoming_channel = parse_short_channel_id(fields[2], "incoming_channel")?;
+	let incoming_index = parse_number(fields[3], "incoming_index")?;
+	let outgoing_channel = parse_short_channel_id(fields[4], "outgoing_channel")?;
+	let block_height = match fields.get(11).map(|field| field.trim()) {
+		None | Some("") => None,
+		Some(field) => Some(parse_number(field, "block_height")?),
+	};
+
+	match fields[0].trim() {
+		"add" => return Ok(LogEvent::Add {
+			timestamp,
+			incoming_channel,
+			incoming_index,
+			outgoing_channel,
+			incoming_amount_msat: MilliSatoshi(parse_number(fields[6], "incoming_amount_msat")?),
+			outgoing_amount_msat: MilliSatoshi(parse_number(fields[7], "outgoing_amount_msat")?),
+			cltv_expiry_delta: parse_number(fields[8], "cltv_expiry_delta")?,
+			endorsed: parse_endorsement(fields[9])?,
+			block_height,
+		}),
+		"resolve" => return Ok(LogEvent::Resolve {
+			timestamp,
+			incoming_channel,
+			incoming_index,
+			outgoing_channel,
+			outgoing_index: parse_number(fields[5], "outgoing_index")?,
+			success: parse_bool(fields[10], "success")?,
+			block_height,
+		}),
+		event => return Err(format!("unknown event: {:?}", event)),
+	}
+}
+
+fn parse_args(args: &[String]) -> Result<SimConfig, String> {
+	let mut sim_config = SimConfig {
+		htlc_limit: 483,
+		liquidity_limit: MilliSatoshi(1_000_000_000),
+		protected_percentage: ManagerConfig::default().protected_percentage,
+		log_path: String::new(),
+	};
+
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--htlc-limit" | "--liquidity-limit" | "--protected-percentage" => {
+				let value = args.next().ok_or(format!("missing value for {}", arg))?;
+				match arg.as_str() {
+					"--htlc-limit" => sim_config.htlc_limit = parse_number(value, arg)?,
+					"--liquidity-limit" => sim_config.liquidity_limit = MilliSatoshi(parse_number(value, arg)?),
+					_ => sim_config.protected_percentage = parse_number(value, arg)?,
+				}
+			},
+			_ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+			_ if sim_config.log_path.is_empty() => sim_config.log_path = arg.clone(),
+			_ => return Err(format!("unexpected argument: {}", arg)),
+		}
+	}
+
+	if sim_config.log_path.is_empty() {
+		return Err(String::from("no log file provided"));
+	}
+
+	if is_json_log(&sim_config.log_path) && !cfg!(feature = "serde") {
+		return Err(format!("{} is a json log, which requires lrc-sim to be built with the serde feature",
+			sim_config.log_path));
+	}
+
+	return Ok(sim_config);
+}
+
+/// Registers a channel the first time that it is seen in the log.
+fn register_channel(resource_manager: &mut ResourceManager<Arc<MockClock>>, registered: &mut HashSet<ShortChannelId>, short_channel_id: ShortChannelId, sim_config: &SimConfig) -> Result<(), String> {
+	if registered.contains(&short_channel_id) {
+		return Ok(());
+	}
+
+	resource_manager.add_channel(short_channel_id, ChannelInfo::new(sim_config.htlc_limit, sim_config.liquidity_limit))
+		.map_err(|e| format!("could not register channel {}: {:?}", short_channel_id, e))?;
+	registered.insert(short_channel_id);
+
+	return Ok(());
+}
+
+fn run(sim_config: SimConfig) -> Result<Summary, String> {
+	let file = File::open(&sim_config.log_path).map_err(|e| format!("could not open {}: {}", sim_config.log_path, e))?;
+
+	let manager_config = ManagerConfig {
+		protected_percentage: sim_config.protected_percentage,
+		..ManagerConfig::default()
+	};
+
+	let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+	let mut resource_manager = ResourceManager::new(manager_config, mock_clock.clone())
+		.map_err(|e| format!("invalid configuration: {:?}", e))?;
+
+	let mut registered = HashSet::new();
+	let mut summary = Summary::default();
+	let mut last_timestamp = SystemTime::UNIX_EPOCH;
+	let mut block_height = 0;
+
+	// CSV logs have a header row, which is skipped.
+	let json_log = is_json_log(&sim_config.log_path);
+	let header_rows = if json_log { 0 } else { 1 };
+
+	for (line_number, line) in BufReader::new(file).lines().enumerate().skip(header_rows) {
+		let line = line.map_err(|e| format!("could not read {}: {}", sim_config.log_path, e))?;
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let event = if json_log { parse_json_line(&line) } else { parse_line(&line) }
+			.map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+		if event.timestamp() < last_timestamp {
+			return Err(format!("line {}: events are not in chronological order", line_number + 1));
+		}
+		last_timestamp = event.timestamp();
+		mock_clock.set(last_timestamp);
+		if let Some(event_height) = event.block_height() {
+			// The manager ignores heights that go backwards, so the height is tracked the same way here.
+			block_height = block_height.max(event_height);
+			resource_manager.update_block_height(event_height)
+				.map_err(|e| format!("line {}: could not update block height: {}", line_number + 1, e))?;
+		}
+
+		match event {
+			LogEvent::Add { incoming_channel, incoming_index, outgoing_channel, incoming_amount_msat, outgoing_amount_msat, cltv_expiry_delta, endorsed, .. } => {
+				register_channel(&mut resource_manager, &mut registered, incoming_channel, &sim_config)?;
+				register_channel(&mut resource_manager, &mut registered, outgoing_channel, &sim_config)?;
+
+				let proposed_htlc = ProposedHTLC::new(incoming_channel, outgoing_channel, incoming_index, endorsed,
+					incoming_amount_msat, outgoing_amount_msat, cltv_expiry_delta, block_height.saturating_add(cltv_expiry_delta));
+
+				match resource_manager.forward_htlc(proposed_htlc) {
+					Ok(forward_decision) => {
+						let reputation_check = forward_decision.reputation_check();
+						println!("add {}:{} -> {}: {:?} (incoming revenue: {:.3}, in flight risk: {:.3}, outgoing revenue: {:.3}, htlc risk: {:.3})",
+							incoming_channel, incoming_index, outgoing_channel, forward_decision.forward_outcome(),
+							reputation_check.incoming_reputation.incoming_revenue, reputation_check.incoming_reputation.in_flight_risk,
+							reputation_check.outgoing_revenue, reputation_check.htlc_risk);
+
+						match forward_decision.forward_outcome() {
+							ForwardOutcome::ForwardOutcomeEndorsed => summary.endorsed += 1,
+							ForwardOutcome::ForwardOutcomeUnendorsed => summary.unendorsed += 1,
+							ForwardOutcome::ForwardOutcomeNoResources => summary.no_resources += 1,
+						}
+					},
+					Err(e) => {
+						println!("add {}:{} -> {}: error: {}", incoming_channel, incoming_index, outgoing_channel, e);
+						summary.forward_errors += 1;
+					},
+				}
+			},
+			LogEvent::Resolve { timestamp, incoming_channel, incoming_index, outgoing_channel, outgoing_index, success, .. } => {
+				let resolved_htlc = ResolvedHTLC::new(timestamp, incoming_channel, incoming_index, outgoing_channel, outgoing_index, success);
+
+				match resource_manager.resolve_htlc(resolved_htlc) {
+					Ok(_) => {
+						println!("resolve {}:{} -> {}: {}", incoming_channel, incoming_index, outgoing_channel,
+							if success { "settled" } else { "failed" });
+						summary.resolved += 1;
+					},
+					Err(e) => {
+						println!("resolve {}:{} -> {}: error: {}", incoming_channel, incoming_index, outgoing_channel, e);
+						summary.resolve_errors += 1;
+					},
+				}
+			},
+		}
+	}
+
+	return Ok(summary);
+}
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+
+	let sim_config = match parse_args(&args) {
+		Ok(sim_config) => sim_config,
+		Err(e) => {
+			eprintln!("{}\n{}", e, USAGE);
+			process::exit(2);
+		},
+	};
+
+	let summary = match run(sim_config) {
+		Ok(summary) => summary,
+		Err(e) => {
+			eprintln!("{}", e);
+			process::exit(1);
+		},
+	};
+
+	println!();
+	println!("endorsed: {}", summary.endorsed);
+	println!("unendorsed: {}", summary.unendorsed);
+	println!("dropped (no resources): {}", summary.no_resources);
+	println!("forward errors: {}", summary.forward_errors);
+	println!("resolved: {}", summary.resolved);
+	println!("resolve errors: {}", summary.resolve_errors);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_line() {
+		let add = parse_line("add,1700000000.5,1,0,800000x1x0,,11000,10000,40,true,").unwrap();
+		assert_eq!(add, LogEvent::Add {
+			timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500),
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 0,
+			outgoing_channel: ShortChannelId::new(800_000, 1, 0).unwrap(),
+			incoming_amount_msat: MilliSatoshi(11_000),
+			outgoing_amount_msat: MilliSatoshi(10_000),
+			cltv_expiry_delta: 40,
+			endorsed: Endorsement::EndorsementTrue,
+			block_height: None,
+		});
+
+		let resolve = parse_line("resolve,1700000010,1,0,2,5,,,,,false,800010").unwrap();
+		assert_eq!(resolve, LogEvent::Resolve {
+			timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_010),
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 0,
+			outgoing_channel: ShortChannelId(2),
+			outgoing_index: 5,
+			success: false,
+			block_height: Some(800_010),
+		});
+		assert_eq!(parse_line("resolve,1700000010,1,0,2,5,,,,,false,").unwrap().block_height(), None);
+
+		assert!(parse_line("add,1700000000,1,0,2,,11000,10000,40,maybe,").is_err());
+		assert!(parse_line("settle,1700000000,1,0,2,,,,,,true").is_err());
+		assert!(parse_line("add,1700000000,1,0,2").is_err());
+		assert!(parse_line("add,1700000000,1,0,2,,11000,10000,40,true,,-1").is_err());
+	}
+
+	#[test]
+	#[cfg(feature = "serde")]
+	fn test_parse_json_line() {
+		let add = parse_json_line(r#"{"event": "add", "timestamp": 1700000000.5, "incoming_channel": 1, "incoming_index": 0,
+			"outgoing_channel": "800000x1x0", "incoming_amount_msat": 11000, "outgoing_amount_msat": 10000,
+			"cltv_expiry_delta": 40, "endorsed": true}"#).unwrap();
+		assert_eq!(add, parse_line("add,1700000000.5,1,0,800000x1x0,,11000,10000,40,true,").unwrap());
+
+		let resolve = parse_json_line(r#"{"event": "resolve", "timestamp": 1700000010, "incoming_channel": 1, "incoming_index": 0,
+			"outgoing_channel": 2, "outgoing_index": 5, "endorsed": null, "success": false, "block_height": 800010}"#).unwrap();
+		assert_eq!(resolve, parse_line("resolve,1700000010,1,0,2,5,,,,,false,800010").unwrap());
+
+		assert!(parse_json_line(r#"{"event": "add", "timestamp": 1700000000, "incoming_channel": 1, "incoming_index": 0,
+			"outgoing_channel": 2, "incoming_amount_msat": 11000, "outgoing_amount_msat": 10000, "cltv_expiry_delta": 40,
+			"endorsed": "maybe"}"#).is_err());
+		// Whole numbers may be written as floats, but fractional amounts are rejected.
+		let float_amounts = parse_json_line(r#"{"event": "add", "timestamp": 1700000000.5, "incoming_channel": 1, "incoming_index": 0,
+			"outgoing_channel": "800000x1x0", "incoming_amount_msat": 1.1e4, "outgoing_amount_msat": 10000.0,
+			"cltv_expiry_delta": 40, "endorsed": true}"#).unwrap();
+		assert_eq!(float_amounts, add);
+		assert!(parse_json_line(r#"{"event": "add", "timestamp": 1700000000, "incoming_channel": 1, "incoming_index": 0,
+			"outgoing_channel": 2, "incoming_amount_msat": 11000.5, "outgoing_amount_msat": 10000, "cltv_expiry_delta": 40,
+			"endorsed": true}"#).is_err());
+
+		assert!(parse_json_line(r#"{"event": "resolve", "timestamp": 1700000010, "incoming_channel": [1]}"#).is_err());
+		assert!(parse_json_line(r#"{"event": "resolve", "timestamp": 1700000010, "incomming_channel": 1}"#).is_err());
+		assert!(parse_json_line("add,1700000000,1,0,2").is_err());
+	}
+
+	#[test]
+	fn test_parse_args() {
+		let args: Vec<String> = vec!["--htlc-limit", "30", "log.csv"].into_iter().map(String::from).collect();
+		let sim_config = parse_args(&args).unwrap();
+		assert_eq!(sim_config.htlc_limit, 30);
+		assert_eq!(sim_config.log_path, "log.csv");
+		assert!(!is_json_log(&sim_config.log_path));
+		assert!(is_json_log("log.jsonl"));
+
+		// JSON logs can only be read if the serde feature is enabled.
+		let args = vec![String::from("log.jsonl")];
+		assert_eq!(parse_args(&args).is_ok(), cfg!(feature = "serde"));
+
+		assert!(parse_args(&[]).is_err());
+		assert!(parse_args(&[String::from("--htlc-limit")]).is_err());
+	}
+}
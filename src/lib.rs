@@ -1,3 +1,4 @@
-
+// The crate uses explicit returns throughout, matching the style of rust-lightning.
+#![allow(clippy::needless_return)]
 
 pub mod resources;
@@ -0,0 +1,285 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::resources::interface::{Bucket, BucketUsage, HTLCClass, ResourceBucketer};
+use crate::resources::resource_bucketing::{BucketConfig, ErrBucketing};
+use crate::resources::types::{MilliSatoshi, ShortChannelId};
+
+/// Tracks the resources available to a single bucket, reserving them with compare-and-swap.
+struct AtomicBucketState {
+	/// Liquidity available.
+	liquidity_msat: AtomicU64,
+	/// Slots available.
+	slots: AtomicU64,
+	/// In flight liquidity locked up.
+	in_flight_liquidity_msat: AtomicU64,
+	/// In flight HTLCs slots locked up.
+	in_flight_slots: AtomicU64,
+}
+
+impl AtomicBucketState {
+	fn new(liquidity_msat: u64, slots: u64) -> Self {
+		return AtomicBucketState {
+			liquidity_msat: AtomicU64::new(liquidity_msat),
+			slots: AtomicU64::new(slots),
+			in_flight_liquidity_msat: AtomicU64::new(0),
+			in_flight_slots: AtomicU64::new(0),
+		};
+	}
+
+	fn has_space(&self, htlc_amount_msat: MilliSatoshi) -> bool {
+		let usage = self.usage();
+		return usage.in_flight_slots < usage.slot_limit &&
+			usage.in_flight_liquidity_msat.checked_add(htlc_amount_msat).is_some_and(|total| total <= usage.liquidity_limit_msat);
+	}
+
+	/// Reserves a slot and then the HTLC's liquidity, releasing the slot if the liquidity is not available.
+	fn add(&self, htlc_amount_msat: MilliSatoshi) -> bool {
+		let slot_limit = self.slots.load(Ordering::Acquire);
+		let slot_reserved = self.in_flight_slots.fetch_update(Ordering::AcqRel, Ordering::Acquire, |slots| {
+			if slots < slot_limit { Some(slots + 1) } else { None }
+		});
+		if slot_reserved.is_err() {
+			return false;
+		}
+
+		let liquidity_limit_msat = self.liquidity_msat.load(Ordering::Acquire);
+		let liquidity_reserved = self.in_flight_liquidity_msat.fetch_update(Ordering::AcqRel, Ordering::Acquire, |liquidity| {
+			liquidity.checked_add(htlc_amount_msat.to_msat()).filter(|total| *total <= liquidity_limit_msat)
+		});
+		if liquidity_reserved.is_err() {
+			self.in_flight_slots.fetch_sub(1, Ordering::AcqRel);
+			return false;
+		}
+
+		return true;
+	}
+
+	/// Occupies resources for a HTLC without checking the bucket's limits, so that HTLCs which were already in
+	/// flight can be tracked even if the bucket has since shrunk. Returns false if the usage would overflow.
+	fn restore(&self, htlc_amount_msat: MilliSatoshi) -> bool {
+		let liquidity_reserved = self.in_flight_liquidity_msat.fetch_update(Ordering::AcqRel, Ordering::Acquire, |liquidity| {
+			liquidity.checked_add(htlc_amount_msat.to_msat())
+		});
+		if liquidity_reserved.is_err() {
+			return false;
+		}
+
+		self.in_flight_slots.fetch_add(1, Ordering::AcqRel);
+		return true;
+	}
+
+	fn remove(&self, htlc_amount_msat: MilliSatoshi) -> Result<bool, ErrBucketing> {
+		self.in_flight_liquidity_msat.fetch_update(Ordering::AcqRel, Ordering::Acquire, |liquidity| {
+			liquidity.checked_sub(htlc_amount_msat.to_msat())
+		}).map_err(|_| ErrBucketing::NoInFlightLiquidity)?;
+
+		let slot_released = self.in_flight_slots.fetch_update(Ordering::AcqRel, Ordering::Acquire, |slots| {
+			slots.checked_sub(1)
+		});
+		if slot_released.is_err() {
+			self.in_flight_liquidity_msat.fetch_add(htlc_amount_msat.to_msat(), Ordering::AcqRel);
+			return Err(ErrBucketing::NoHTLCSlotsOccupied);
+		}
+
+		return Ok(true);
+	}
+
+	fn usage(&self) -> BucketUsage {
+		return BucketUsage {
+			in_flight_slots: self.in_flight_slots.load(Ordering::Acquire),
+			slot_limit: self.slots.load(Ordering::Acquire),
+			in_flight_liquidity_msat: MilliSatoshi(self.in_flight_liquidity_msat.load(Ordering::Acquire)),
+			liquidity_limit_msat: MilliSatoshi(self.liquidity_msat.load(Ordering::Acquire)),
+		};
+	}
+}
+
+/// A resource bucketer that splits a channel's resources into buckets in the same way as
+/// [`crate::resources::resource_bucketing::BucketResourceManager`], but reserves them with atomic operations
+/// rather than taking a lock.
+///
+/// Slots and liquidity are reserved one after the other, so a HTLC may briefly hold a slot that it releases
+/// once it finds that there is not enough liquidity. Concurrent HTLCs may be refused while this happens, but
+/// new HTLCs never take a bucket over its limits. Per-incoming fair shares are not supported.
+pub struct AtomicBucketResourceManager {
+	config: BucketConfig,
+	/// The state of each bucket, indexed by bucket.
+	buckets: Vec<AtomicBucketState>,
+}
+
+impl AtomicBucketResourceManager {
+	/// Creates a bucketer that reserves a percentage of resources for protected HTLCs.
+	pub fn new(total_liquidity_msat: MilliSatoshi, total_slots: u64, protected_percentage: u64) -> Result<Self, ErrBucketing> {
+		if protected_percentage > 100 {
+			return Err(ErrBucketing::ProtectedPercentage);
+		}
+
+		return AtomicBucketResourceManager::with_config(total_liquidity_msat, total_slots, BucketConfig::protected(protected_percentage));
+	}
+
+	/// Creates a bucketer that splits resources according to the configuration provided.
+	pub fn with_config(total_liquidity_msat: MilliSatoshi, total_slots: u64, config: BucketConfig) -> Result<Self, ErrBucketing> {
+		config.validate()?;
+
+		if config.buckets.iter().any(|tier| tier.fair_share_percentage.is_some()) {
+			return Err(ErrBucketing::FairShareUnsupported);
+		}
+
+		let buckets = config.split(total_liquidity_msat.to_msat(), total_slots)?.into_iter()
+			.map(|(liquidity_msat, slots)| AtomicBucketState::new(liquidity_msat, slots))
+			.collect();
+
+		return Ok(AtomicBucketResourceManager {
+			config,
+			buckets,
+		});
+	}
+
+	fn bucket(&self, bucket: Bucket) -> Option<&AtomicBucketState> {
+		return self.buckets.get(bucket.0 as usize);
+	}
+}
+
+impl ResourceBucketer for AtomicBucketResourceManager {
+	fn add_htlc(&self, _incoming_channel: ShortChannelId, htlc_class: HTLCClass, htlc_amount_msat: MilliSatoshi) -> Option<Bucket> {
+		let mut bucket = self.config.assigned_bucket(htlc_class);
+		loop {
+			if self.bucket(bucket)?.add(htlc_amount_msat) {
+				return Some(bucket);
+			}
+
+			bucket = self.config.buckets[bucket.0 as usize].overflow?;
+		}
+	}
+
+	fn available_bucket(&self, _incoming_channel: ShortChannelId, htlc_class: HTLCClass, htlc_amount_msat: MilliSatoshi) -> Option<Bucket> {
+		let mut bucket = self.config.assigned_bucket(htlc_class);
+		loop {
+			if self.bucket(bucket)?.has_space(htlc_amount_msat) {
+				return Some(bucket);
+			}
+
+			bucket = self.config.buckets[bucket.0 as usize].overflow?;
+		}
+	}
+
+	fn restore_htlc(&self, _incoming_channel: ShortChannelId, bucket: Bucket, htlc_amount_msat: MilliSatoshi) -> bool {
+		return self.bucket(bucket).is_some_and(|state| state.restore(htlc_amount_msat));
+	}
+
+	fn remove_htlc(&self, _incoming_channel: ShortChannelId, bucket: Bucket, htlc_amount_msat: MilliSatoshi) -> Result<bool, ErrBucketing> {
+		return self.bucket(bucket).ok_or(ErrBucketing::UnknownBucket)?.remove(htlc_amount_msat);
+	}
+
+	fn occupancy(&self) -> Vec<BucketUsage> {
+		return self.buckets.iter().map(|state| state.usage()).collect();
+	}
+
+	fn resize(&self, total_liquidity_msat: MilliSatoshi, total_slots: u64) -> Result<(), ErrBucketing> {
+		// Each limit is updated on its own, so HTLCs added while resizing are checked against old and new limits.
+		for (state, (liquidity_msat, slots)) in self.buckets.iter().zip(self.config.split(total_liquidity_msat.to_msat(), total_slots)?) {
+			state.liquidity_msat.store(liquidity_msat, Ordering::Release);
+			state.slots.store(slots, Ordering::Release);
+		}
+
+		return Ok(());
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+	use super::*;
+
+	use std::sync::Arc;
+	use std::thread;
+
+	#[test]
+	fn test_atomic_bucket_resource_manager() {
+		let bucketer = AtomicBucketResourceManager::new(MilliSatoshi(100_000), 4, 50).unwrap();
+		let protected = HTLCClass::EndorsedSufficientReputation;
+
+		// Protected HTLCs fill the protected bucket, then overflow into the general bucket.
+		assert_eq!(bucketer.add_htlc(ShortChannelId(1), protected, MilliSatoshi(40_000)), Some(Bucket::PROTECTED));
+		assert_eq!(bucketer.add_htlc(ShortChannelId(1), protected, MilliSatoshi(20_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucketer.add_htlc(ShortChannelId(1), HTLCClass::Unendorsed, MilliSatoshi(40_000)), None);
+		assert_eq!(bucketer.occupancy()[1].in_flight_slots, 1);
+
+		assert_eq!(bucketer.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(20_000)), Ok(true));
+		assert_eq!(bucketer.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(20_000)), Err(ErrBucketing::NoInFlightLiquidity));
+		assert_eq!(bucketer.remove_htlc(ShortChannelId(1), Bucket(2), MilliSatoshi(1)), Err(ErrBucketing::UnknownBucket));
+
+		// Restored HTLCs reoccupy their bucket even if it has been shrunk below its usage.
+		assert_eq!(bucketer.resize(MilliSatoshi(20_000), 2), Ok(()));
+		assert_eq!(bucketer.restore_htlc(ShortChannelId(1), Bucket::PROTECTED, MilliSatoshi(10_000)), true);
+		assert_eq!(bucketer.occupancy()[0], BucketUsage {
+			in_flight_slots: 2,
+			slot_limit: 1,
+			in_flight_liquidity_msat: MilliSatoshi(50_000),
+			liquidity_limit_msat: MilliSatoshi(10_000),
+		});
+		assert_eq!(bucketer.restore_htlc(ShortChannelId(1), Bucket(2), MilliSatoshi(1)), false);
+
+		let fair_share = BucketConfig::protected(50).with_general_fair_share(50);
+		assert_eq!(AtomicBucketResourceManager::with_config(MilliSatoshi(100_000), 4, fair_share).err(), Some(ErrBucketing::FairShareUnsupported));
+	}
+
+	#[test]
+	fn test_atomic_bucket_resource_manager_concurrent() {
+		let bucketer = Arc::new(AtomicBucketResourceManager::new(MilliSatoshi(1_000_000), 400, 50).unwrap());
+
+		// Each thread holds a window of HTLCs of varying sizes, resolving the oldest before adding another.
+		let handles: Vec<_> = (0..8u64).map(|i| {
+			let bucketer = bucketer.clone();
+			thread::spawn(move || {
+				let mut in_flight = std::collections::VecDeque::new();
+				let mut seed = i + 1;
+				for _ in 0..5_000 {
+					seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+					let amount = 1_000 + (seed >> 33) % 20_000;
+					let class = if seed % 2 == 0 { HTLCClass::EndorsedSufficientReputation } else { HTLCClass::Unendorsed };
+
+					if let Some(bucket) = bucketer.add_htlc(ShortChannelId(i), class, MilliSatoshi(amount)) {
+						in_flight.push_back((bucket, amount));
+					}
+
+					for state in bucketer.occupancy() {
+						assert!(state.in_flight_slots <= state.slot_limit);
+						assert!(state.in_flight_liquidity_msat <= state.liquidity_limit_msat);
+					}
+
+					if in_flight.len() > 40 {
+						let (bucket, amount) = in_flight.pop_front().unwrap();
+						assert_eq!(bucketer.remove_htlc(ShortChannelId(i), bucket, MilliSatoshi(amount)), Ok(true));
+					}
+				}
+
+				for (bucket, amount) in in_flight {
+					assert_eq!(bucketer.remove_htlc(ShortChannelId(i), bucket, MilliSatoshi(amount)), Ok(true));
+				}
+			})
+		}).collect();
+
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		for state in bucketer.occupancy() {
+			assert_eq!(state.in_flight_slots, 0);
+			assert_eq!(state.in_flight_liquidity_msat, MilliSatoshi(0));
+		}
+
+		// When threads race to fill a bucket, exactly as many HTLCs as there are slots are accepted.
+		let bucketer = Arc::new(AtomicBucketResourceManager::new(MilliSatoshi(1_000_000), 400, 0).unwrap());
+		let handles: Vec<_> = (0..8).map(|i| {
+			let bucketer = bucketer.clone();
+			thread::spawn(move || {
+				return (0..100).filter(|_| bucketer.add_htlc(ShortChannelId(i), HTLCClass::Unendorsed, MilliSatoshi(1_000)).is_some()).count();
+			})
+		}).collect();
+
+		let accepted: usize = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+		assert_eq!(accepted, 400);
+		assert_eq!(bucketer.occupancy()[1].in_flight_liquidity_msat, MilliSatoshi(400_000));
+	}
+}
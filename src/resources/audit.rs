@@ -0,0 +1,213 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "serde")]
+use std::io::{self, Write};
+#[cfg(feature = "serde")]
+use std::path::Path;
+#[cfg(feature = "serde")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::resources::interface::{BucketUsage, ForwardDecision, InFlightHTLC, ProposedHTLC, ResolvedHTLC};
+
+/// This records a forwarding decision made by the resource manager.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ForwardAudit {
+	/// The time at which the decision was made.
+	pub timestamp: SystemTime,
+	/// The HTLC that was proposed for forwarding.
+	pub proposed_htlc: ProposedHTLC,
+	/// The decision made for the HTLC, including the reputation values used to make it.
+	pub decision: ForwardDecision,
+	/// The usage of each of the outgoing channel's buckets once the decision was applied, indexed by bucket.
+	pub bucket_occupancy: Vec<BucketUsage>,
+}
+
+/// This records the resolution of a HTLC that was previously forwarded.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResolveAudit {
+	/// The resolution that was reported.
+	pub resolved_htlc: ResolvedHTLC,
+	/// The HTLC as it was tracked while in flight.
+	pub in_flight_htlc: InFlightHTLC,
+	/// The fees credited to the incoming channel's reputation for the HTLC.
+	pub effective_fees: f64,
+}
+
+/// This records a forward that was cancelled because it could not be added to the outgoing channel.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CancelAudit {
+	/// The time at which the forward was cancelled.
+	pub timestamp: SystemTime,
+	/// The HTLC as it was tracked while in flight.
+	pub in_flight_htlc: InFlightHTLC,
+}
+
+/// This is an entry in the resource manager's audit log.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuditEvent {
+	Forward(ForwardAudit),
+	Resolve(ResolveAudit),
+	Cancel(CancelAudit),
+}
+
+/// This is an interface for recording every decision and resolution made by the resource manager, so that
+/// forwarding outcomes can be explained after the fact.
+///
+/// Sinks are called while the incoming channel's reputation is locked, so should not block for long.
+pub trait AuditSink {
+	/// This records an event. Failing to record an event must not affect forwarding, so errors are left to
+	/// the sink to surface.
+	fn record(&self, event: &AuditEvent);
+}
+
+/// An audit sink that keeps the most recent events in memory.
+pub struct RingBufferSink {
+	capacity: usize,
+	events: Mutex<VecDeque<AuditEvent>>,
+}
+
+impl RingBufferSink {
+	pub fn new(capacity: usize) -> Self {
+		return RingBufferSink {
+			capacity,
+			events: Mutex::new(VecDeque::with_capacity(capacity)),
+		};
+	}
+
+	/// Returns the events held by the buffer, oldest first.
+	pub fn events(&self) -> Vec<AuditEvent> {
+		return match self.events.lock() {
+			Ok(events) => events.iter().cloned().collect(),
+			Err(_) => Vec::new(),
+		};
+	}
+}
+
+impl AuditSink for RingBufferSink {
+	fn record(&self, event: &AuditEvent) {
+		if self.capacity == 0 {
+			return;
+		}
+
+		if let Ok(ref mut events) = self.events.lock() {
+			if events.len() == self.capacity {
+				events.pop_front();
+			}
+			events.push_back(event.clone());
+		}
+	}
+}
+
+/// An audit sink that appends each event to a file as a line of JSON.
+#[cfg(feature = "serde")]
+pub struct JsonLinesSink {
+	file: Mutex<File>,
+	/// The number of events that could not be written.
+	write_failures: AtomicU64,
+}
+
+#[cfg(feature = "serde")]
+impl JsonLinesSink {
+	/// Opens the file at the path provided for appending, creating it if it does not exist.
+	pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+		return Ok(JsonLinesSink {
+			file: Mutex::new(file),
+			write_failures: AtomicU64::new(0),
+		});
+	}
+
+	/// Returns the number of events that could not be written to the file.
+	pub fn write_failures(&self) -> u64 {
+		return self.write_failures.load(Ordering::Relaxed);
+	}
+
+	fn write_event(&self, event: &AuditEvent) -> Result<(), io::Error> {
+		let mut line = serde_json::to_vec(event)?;
+		line.push(b'\n');
+
+		let mut file = self.file.lock().map_err(|_| io::Error::other("audit file lock poisoned"))?;
+		return file.write_all(&line);
+	}
+}
+
+#[cfg(feature = "serde")]
+impl AuditSink for JsonLinesSink {
+	fn record(&self, event: &AuditEvent) {
+		if self.write_event(event).is_err() {
+			self.write_failures.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::resources::interface::{Bucket, Endorsement, ForwardOutcome};
+	use crate::resources::types::{MilliSatoshi, ShortChannelId};
+
+	fn resolve_event(incoming_index: u32) -> AuditEvent {
+		let proposed_htlc = ProposedHTLC::new(ShortChannelId(1), ShortChannelId(2), incoming_index, Endorsement::EndorsementFalse, MilliSatoshi(1_100), MilliSatoshi(1_000), 40, 40);
+
+		return AuditEvent::Resolve(ResolveAudit {
+			resolved_htlc: ResolvedHTLC::new(SystemTime::UNIX_EPOCH, ShortChannelId(1), incoming_index, ShortChannelId(2), 0, true),
+			in_flight_htlc: InFlightHTLC {
+				timestamp_added: SystemTime::UNIX_EPOCH,
+				outgoing_decision: ForwardOutcome::ForwardOutcomeUnendorsed,
+				bucket: Some(Bucket::GENERAL),
+				expiry_height: Some(40),
+				outgoing_index: Some(0),
+				proposed_htlc,
+			},
+			effective_fees: 100.0,
+		});
+	}
+
+	#[test]
+	fn test_ring_buffer_sink() {
+		let sink = RingBufferSink::new(2);
+		for i in 0..3 {
+			sink.record(&resolve_event(i));
+		}
+
+		// Only the most recent events are kept.
+		let indexes: Vec<u32> = sink.events().iter().map(|event| match event {
+			AuditEvent::Resolve(resolve) => resolve.resolved_htlc.incoming_index,
+			AuditEvent::Forward(forward) => forward.proposed_htlc.incoming_index,
+			AuditEvent::Cancel(cancel) => cancel.in_flight_htlc.proposed_htlc.incoming_index,
+		}).collect();
+		assert_eq!(indexes, vec![1, 2]);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_json_lines_sink() {
+		let path = std::env::temp_dir().join(format!("lrc-audit-{}.jsonl", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+
+		let sink = JsonLinesSink::new(&path).unwrap();
+		sink.record(&resolve_event(0));
+		sink.record(&resolve_event(1));
+		assert_eq!(sink.write_failures(), 0);
+
+		let contents = std::fs::read_to_string(&path).unwrap();
+		let events: Vec<AuditEvent> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+		assert_eq!(events.len(), 2);
+		match &events[1] {
+			AuditEvent::Resolve(resolve) => assert_eq!(resolve.effective_fees, 100.0),
+			_ => panic!("expected resolve event"),
+		}
+
+		std::fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,82 @@
+use core::time::Duration;
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// An interface that provides the current time to time-dependent components, so that
+/// they can be driven by simulated time.
+pub trait Clock {
+	/// Returns the current time.
+	fn now(&self) -> SystemTime;
+}
+
+/// A clock that reads the system's wall clock time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> SystemTime {
+		return SystemTime::now();
+	}
+}
+
+/// A clock that only moves when it is explicitly advanced, for use in tests and simulations.
+pub struct MockClock {
+	now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+	pub fn new(start_time: SystemTime) -> Self {
+		return MockClock {
+			now: Mutex::new(start_time),
+		};
+	}
+
+	/// Moves the clock forward by the duration provided.
+	pub fn advance(&self, duration: Duration) {
+		*self.lock_now() += duration;
+	}
+
+	/// Sets the clock to a specific time, which may be before the current time.
+	pub fn set(&self, time: SystemTime) {
+		*self.lock_now() = time;
+	}
+
+	/// Locks the clock's time. A poisoned lock is recovered, as the time is always left in a valid state.
+	fn lock_now(&self) -> MutexGuard<'_, SystemTime> {
+		return self.now.lock().unwrap_or_else(|err| err.into_inner());
+	}
+}
+
+impl Clock for MockClock {
+	fn now(&self) -> SystemTime {
+		return *self.lock_now();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_mock_clock() {
+		let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+		let mock_clock = MockClock::new(start_time);
+		assert_eq!(mock_clock.now(), start_time);
+
+		mock_clock.advance(Duration::from_secs(60));
+		assert_eq!(mock_clock.now(), start_time + Duration::from_secs(60));
+
+		mock_clock.set(start_time);
+		assert_eq!(mock_clock.now(), start_time);
+
+		// The clock keeps working if a thread panics while holding its lock.
+		let _ = std::thread::scope(|s| s.spawn(|| {
+			let _now = mock_clock.now.lock().unwrap();
+			panic!("poison clock");
+		}).join());
+		assert!(mock_clock.now.is_poisoned());
+		mock_clock.advance(Duration::from_secs(60));
+		assert_eq!(mock_clock.now(), start_time + Duration::from_secs(60));
+		mock_clock.set(start_time);
+		assert_eq!(mock_clock.now(), start_time);
+	}
+}
@@ -1,104 +1,120 @@
 
+use core::fmt;
 use core::time::{Duration};
-use std::time::Instant;
-use std::ops::Sub;
+use std::time::SystemTime;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
-enum ErrDecayingAverage {
+pub enum ErrDecayingAverage {
 	TimeAdditionError,
 }
 
+impl fmt::Display for ErrDecayingAverage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ErrDecayingAverage::TimeAdditionError => write!(f, "value added before last update"),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct DecayingAverageStart {
-	pub(crate) last_update: Instant,
+	pub(crate) last_update: SystemTime,
 	pub(crate) value: f64,
 }
 
 pub(crate) struct DecayingAverage {
-	last_update: Instant,
+	last_update: SystemTime,
 	value: f64,
 	decay_rate: f64,
 }
 
 fn calculate_decay_rate(period: Duration) -> f64 {
-	return (0.5 as f64).powf(2 as f64 / period.as_secs() as f64);
+	return 0.5_f64.powf(2.0 / period.as_secs() as f64);
 }
 
 impl DecayingAverage {
 	pub(crate) fn new(period: Duration, start_value: DecayingAverageStart) -> Self {
-
-		let mut last_update = Instant::now();
-		let mut value = 0.0;
-
-		//TODO: complete check ?
-		if start_value.value != 0.0 {
-			last_update = start_value.last_update;
-			value = start_value.value;
-		}
-
 		return DecayingAverage {
-			last_update: last_update,
-			value: value,
+			last_update: start_value.last_update,
+			value: start_value.value,
 			decay_rate: calculate_decay_rate(period),
 		}
 	}
 
-	fn update(&mut self, update_time: Instant) {
-		let last_update_diff = update_time.sub(update_time);
+	/// Returns the average's value as of its last update, without applying any decay.
+	pub(crate) fn snapshot(&self) -> DecayingAverageStart {
+		return DecayingAverageStart {
+			last_update: self.last_update,
+			value: self.value,
+		};
+	}
+
+	/// Decays the average to the time provided. Times before our last update leave the value unchanged.
+	fn update(&mut self, update_time: SystemTime) {
+		let last_update_diff = match update_time.duration_since(self.last_update) {
+			Ok(diff) => diff,
+			Err(_) => return,
+		};
 
 		if last_update_diff == Duration::from_secs(0) {
 			return;
 		}
 
-		self.value = self.value * self.decay_rate.powf(last_update_diff.as_secs() as f64);
+		self.value *= self.decay_rate.powf(last_update_diff.as_secs_f64());
 		self.last_update = update_time;
 	}
 
-	pub(crate) fn add(&mut self, value: f64) {
-		self.add_time(value, Instant::now());
-	}
-
-	pub(crate) fn get_value(&mut self) -> f64 {
-		self.update(Instant::now());
+	/// Returns the value of the average, decayed to the time provided.
+	pub(crate) fn get_value(&mut self, now: SystemTime) -> f64 {
+		self.update(now);
 		return self.value;
 	}
 
 	/// We cannot add a value at a specific timestamp that is before our last update.
-	fn add_time(&mut self, value: f64, specific_timestamp: Instant) -> Result<bool, ErrDecayingAverage> {
-		if !self.last_update.checked_duration_since(specific_timestamp).is_none() {
+	pub(crate) fn add(&mut self, value: f64, specific_timestamp: SystemTime) -> Result<bool, ErrDecayingAverage> {
+		if specific_timestamp < self.last_update {
 			return Err(ErrDecayingAverage::TimeAdditionError);
 		}
 
 		self.update(specific_timestamp);
 		self.value += value;
-		self.last_update = specific_timestamp;
 
 		return Ok(true);
 	}
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 	use super::*;
-	use std::thread::sleep;
 
 	#[test]
 	fn test_decaying_average() {
 		let decaying_average_start = DecayingAverageStart {
-			last_update: Instant::now(),
+			last_update: SystemTime::UNIX_EPOCH,
 			value: 0.0,
 		};
-		let decaying_average = DecayingAverage::new(Duration::from_secs(0), decaying_average_start);
+		let mut decaying_average = DecayingAverage::new(Duration::from_secs(0), decaying_average_start);
+		assert_eq!(decaying_average.get_value(SystemTime::UNIX_EPOCH), 0.0);
 	}
 
 	#[test]
 	fn test_decaying_average_add_time() {
+		let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
 		let decaying_average_start = DecayingAverageStart {
-			last_update: Instant::now(),
+			last_update: start_time,
 			value: 0.0,
 		};
-		let mut decaying_average = DecayingAverage::new(Duration::from_secs(0), decaying_average_start);
-		sleep(Duration::new(1, 0));
-		let ret = decaying_average.add_time(1.0, Instant::now());	
+		let mut decaying_average = DecayingAverage::new(Duration::from_secs(60), decaying_average_start);
+
+		let ret = decaying_average.add(100.0, start_time + Duration::from_secs(1));
 		assert_eq!(ret.is_ok(), true);
+
+		// The value halves over half of the period.
+		let value = decaying_average.get_value(start_time + Duration::from_secs(31));
+		assert!((value - 50.0).abs() < 1e-9);
+
+		let ret = decaying_average.add(1.0, start_time);
+		assert_eq!(ret, Err(ErrDecayingAverage::TimeAdditionError));
 	}
 }
@@ -0,0 +1,114 @@
+use core::fmt;
+
+use crate::resources::decaying_average::ErrDecayingAverage;
+use crate::resources::interface::NodeId;
+use crate::resources::reputation_tracker::ErrReputation;
+use crate::resources::resource_bucketing::ErrBucketing;
+use crate::resources::types::{MilliSatoshi, ShortChannelId};
+
+/// The error type returned by the resource manager and the trackers that it is composed of. Where an error
+/// relates to a specific channel or HTLC, the offending short channel id and HTLC index are included.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ErrResourceManager {
+	/// The manager's configuration is invalid.
+	InvalidConfig,
+	/// A channel with this short channel id is already registered.
+	ChannelExists(ShortChannelId),
+	/// No channel with this short channel id is registered.
+	ChannelNotFound(ShortChannelId),
+	/// The information provided for a channel is invalid.
+	InvalidChannelInfo(ShortChannelId),
+	/// A HTLC was proposed from a channel that is not registered as an incoming channel.
+	UnknownIncomingChannel(ShortChannelId),
+	/// A HTLC was proposed over a channel that is not registered as an outgoing channel.
+	UnknownOutgoingChannel(ShortChannelId),
+	/// A HTLC was proposed with an outgoing amount greater than the total supply of bitcoin.
+	AmountAboveMaximum { incoming_channel: ShortChannelId, incoming_index: u32, amount_msat: MilliSatoshi },
+	/// A HTLC was proposed with an outgoing amount greater than its incoming amount, so it would cost us to forward.
+	NegativeFee { incoming_channel: ShortChannelId, incoming_index: u32, incoming_amount_msat: MilliSatoshi, outgoing_amount_msat: MilliSatoshi },
+	/// A HTLC was resolved on a different outgoing channel to the one it was forwarded over.
+	OutgoingChannelMismatch { incoming_channel: ShortChannelId, incoming_index: u32, expected: ShortChannelId, actual: ShortChannelId },
+	/// A HTLC was resolved with a different outgoing index to the one it was assigned.
+	OutgoingIndexMismatch { incoming_channel: ShortChannelId, incoming_index: u32, expected: u32, actual: u32 },
+	/// An attempt was made to release the resources of a HTLC that was never given any.
+	NoResourcesAssigned { incoming_channel: ShortChannelId, incoming_index: u32 },
+	/// A thread panicked while holding the lock on this channel's state.
+	ChannelLockPoisoned(ShortChannelId),
+	/// A thread panicked while holding the lock on this peer's reputation.
+	PeerLockPoisoned(NodeId),
+	/// A thread panicked while holding the lock on the manager's state.
+	ManagerLockPoisoned,
+	/// An outgoing channel no longer has space for a HTLC that was previously in flight over it.
+	InsufficientResources(ShortChannelId),
+	/// Tracking the reputation of an incoming channel failed.
+	Reputation { incoming_channel: ShortChannelId, incoming_index: u32, err: ErrReputation },
+	/// Updating the resource buckets of an outgoing channel failed.
+	Bucketing { outgoing_channel: ShortChannelId, err: ErrBucketing },
+	/// Updating the decaying average that tracks a channel's revenue or reputation failed.
+	DecayingAverage { short_channel_id: ShortChannelId, err: ErrDecayingAverage },
+}
+
+impl fmt::Display for ErrResourceManager {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ErrResourceManager::InvalidConfig => write!(f, "invalid manager configuration"),
+			ErrResourceManager::ChannelExists(scid) => write!(f, "channel {} already registered", scid),
+			ErrResourceManager::ChannelNotFound(scid) => write!(f, "channel {} not registered", scid),
+			ErrResourceManager::InvalidChannelInfo(scid) => write!(f, "invalid channel info for channel {}", scid),
+			ErrResourceManager::UnknownIncomingChannel(scid) => write!(f, "unknown incoming channel {}", scid),
+			ErrResourceManager::UnknownOutgoingChannel(scid) => write!(f, "unknown outgoing channel {}", scid),
+			ErrResourceManager::AmountAboveMaximum { incoming_channel, incoming_index, amount_msat } =>
+				write!(f, "htlc {}:{} amount {} above maximum", incoming_channel, incoming_index, amount_msat),
+			ErrResourceManager::NegativeFee { incoming_channel, incoming_index, incoming_amount_msat, outgoing_amount_msat } =>
+				write!(f, "htlc {}:{} outgoing amount {} exceeds incoming amount {}", incoming_channel, incoming_index,
+					outgoing_amount_msat, incoming_amount_msat),
+			ErrResourceManager::OutgoingChannelMismatch { incoming_channel, incoming_index, expected, actual } =>
+				write!(f, "htlc {}:{} resolved on outgoing channel {}, forwarded on {}", incoming_channel, incoming_index, actual, expected),
+			ErrResourceManager::OutgoingIndexMismatch { incoming_channel, incoming_index, expected, actual } =>
+				write!(f, "htlc {}:{} resolved with outgoing index {}, assigned {}", incoming_channel, incoming_index, actual, expected),
+			ErrResourceManager::NoResourcesAssigned { incoming_channel, incoming_index } =>
+				write!(f, "htlc {}:{} was not assigned resources", incoming_channel, incoming_index),
+			ErrResourceManager::ChannelLockPoisoned(scid) => write!(f, "channel {} lock poisoned", scid),
+			ErrResourceManager::PeerLockPoisoned(node_id) => write!(f, "peer {} lock poisoned", node_id),
+			ErrResourceManager::ManagerLockPoisoned => write!(f, "manager lock poisoned"),
+			ErrResourceManager::InsufficientResources(scid) => write!(f, "insufficient resources on channel {}", scid),
+			ErrResourceManager::Reputation { incoming_channel, incoming_index, err } =>
+				write!(f, "htlc {}:{} reputation: {}", incoming_channel, incoming_index, err),
+			ErrResourceManager::Bucketing { outgoing_channel, err } =>
+				write!(f, "channel {} bucketing: {}", outgoing_channel, err),
+			ErrResourceManager::DecayingAverage { short_channel_id, err } =>
+				write!(f, "channel {} decaying average: {}", short_channel_id, err),
+		}
+	}
+}
+
+impl std::error::Error for ErrResourceManager {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_error_display() {
+		let err = ErrResourceManager::Reputation {
+			incoming_channel: ShortChannelId::new(800_000, 1, 0).unwrap(),
+			incoming_index: 2,
+			err: ErrReputation::ResolutionNotFound,
+		};
+		assert_eq!(err.to_string(), "htlc 800000x1x0:2 reputation: resolution not found");
+
+		let err = ErrResourceManager::Bucketing {
+			outgoing_channel: ShortChannelId(3),
+			err: ErrBucketing::NoHTLCSlotsOccupied,
+		};
+		assert_eq!(err.to_string(), "channel 0x0x3 bucketing: no htlc slots occupied");
+
+		let err = ErrResourceManager::NegativeFee {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 2,
+			incoming_amount_msat: MilliSatoshi(1_000),
+			outgoing_amount_msat: MilliSatoshi(1_100),
+		};
+		assert_eq!(err.to_string(), "htlc 0x0x1:2 outgoing amount 1100 msat exceeds incoming amount 1000 msat");
+	}
+}
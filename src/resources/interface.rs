@@ -1,56 +1,96 @@
+use core::fmt;
 
-use std::time::Instant;
+use std::time::{Duration, SystemTime};
+use crate::resources::error::ErrResourceManager;
 use crate::resources::resource_bucketing::ErrBucketing;
-use crate::resources::reputation_tracker::ErrReputation;
+use crate::resources::types::{MilliSatoshi, ShortChannelId};
 
 /// An interface representing an entity that tracks the reputation of
 /// channel peers based on HTLC forwarding behavior.
 pub trait LocalResourceManager {
 	/// This updates the reputation manager to reflect that a proposed HTLC has been forwarded.
 	///
-	/// It requires the forwarding restrictions of the outgoing channel to implement bucketing appropriately.
-	fn forward_htlc(&mut self, proposed_htlc: ProposedHTLC, chan_info: ChannelInfo) -> Result<ForwardDecision, ()>;
+	/// Both the incoming and outgoing channel must have been registered with the manager, as the
+	/// forwarding restrictions of the outgoing channel are required to implement bucketing appropriately.
+	fn forward_htlc(&mut self, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager>;
 	/// This updates the reputation manager to reflect that an in-flight htlc has been resolved. It returns
 	/// the in-flight HTLC as tracked by the manager. It will error if the HTLC is not found.
 	///
 	/// Note, that this API expects resolution to be reported for *all* HTLCs, even if the decision to forward
 	/// the HTLC was that we have no resources for the forward - this function must still be used to indicate
 	/// that the HTLC has been cleared from our state (as it would have been locked in our incoming link).
-	fn resolve_htlc(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ()>;
+	fn resolve_htlc(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrResourceManager>;
+	/// This records the index that a forwarded HTLC was assigned on its outgoing channel, completing its circuit.
+	/// Once assigned, the HTLC must be resolved with the same outgoing index.
+	fn assign_outgoing_index(&mut self, incoming_channel: ShortChannelId, incoming_index: u32, outgoing_index: u32) -> Result<(), ErrResourceManager>;
+	/// This undoes a forward that could not be added to the outgoing channel, releasing its resources and
+	/// returning the in-flight HTLC as tracked by the manager. Unlike resolution, the HTLC does not affect the
+	/// reputation of the incoming channel. It will error if the HTLC is not found.
+	fn cancel_forward(&mut self, incoming_channel: ShortChannelId, incoming_index: u32) -> Result<InFlightHTLC, ErrResourceManager>;
 }
 
 /// This contains the action that should be taken for forwarding a HTLC and debugging details of the values used.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ForwardDecision {
 	/// This contains the numerical values used in making a reputation decision. 
 	pub(crate) reputation_check: ReputationCheck,
 	/// This is the action that the caller should take.
 	pub(crate) forward_outcome: ForwardOutcome,
+	/// This is the bucket that the HTLC was assigned to, if it was given resources.
+	pub(crate) bucket: Option<Bucket>,
+	/// This is the endorsement signal that should be set on the outgoing HTLC.
+	pub(crate) outgoing_endorsement: Endorsement,
 }
 
-#[derive(Clone)]
-pub(crate) struct IncomingReputation {
+impl ForwardDecision {
+	/// Returns the action that the caller should take for the HTLC.
+	pub fn forward_outcome(&self) -> ForwardOutcome {
+		return self.forward_outcome.clone();
+	}
+
+	/// Returns the bucket that the HTLC was assigned to, if it was given resources.
+	pub fn bucket(&self) -> Option<Bucket> {
+		return self.bucket;
+	}
+
+	/// Returns the numerical values used in making the reputation decision.
+	pub fn reputation_check(&self) -> ReputationCheck {
+		return self.reputation_check.clone();
+	}
+
+	/// Returns the endorsement signal that should be set on the outgoing HTLC, as determined by the manager's
+	/// endorsement policy. This is EndorsementNone if the HTLC should not be forwarded.
+	pub fn outgoing_endorsement(&self) -> Endorsement {
+		return self.outgoing_endorsement.clone();
+	}
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IncomingReputation {
 	/// Represents the reputation that the forwarding channel has accrued over time.
-	pub(crate) incoming_revenue: f64,
+	pub incoming_revenue: f64,
 	/// Represents the outstanding risk of all of the forwarding party's currently in flight HTLCs.
-	pub(crate) in_flight_risk: f64,
+	pub in_flight_risk: f64,
 }
 
 /// This provides the reputation scores that are used to make a forwarding decision for a HTLC.
 ///
 /// These are surfaced for the sake of debugging and simulation, and wouldn't be used much in a production
 /// implementation.
-#[derive(Clone)]
-pub(crate) struct ReputationCheck {
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReputationCheck {
 	/// Represents the reputation that has been built up by the incoming link, and any outstanding
 	/// risk that it poses to us.
-	pub(crate) incoming_reputation: IncomingReputation,
+	pub incoming_reputation: IncomingReputation,
 	/// Represents the cost of using the outgoing link, evaluated based on how valuable it has been
 	/// to us in the past.
-	pub(crate) outgoing_revenue: f64,
+	pub outgoing_revenue: f64,
 	/// Represents the risk of newly proposed HTLC, should it be used to jam our channel for its full
 	/// expiry time.
-	pub(crate) htlc_risk: f64,
+	pub htlc_risk: f64,
 }
 
 impl ReputationCheck {
@@ -61,8 +101,9 @@ impl ReputationCheck {
 }
 
 /// This represents the various forwarding outcomes for a proposed HTLC forward.
-#[derive(Clone, PartialEq)]
-pub(crate) enum ForwardOutcome {
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ForwardOutcome {
 	/// This means that a HTLC should be dropped because the resource bucket that it qualifies for is full.
 	ForwardOutcomeNoResources,
 	/// This means that the HTLC should be forwarded but not endorsed.
@@ -71,42 +112,113 @@ pub(crate) enum ForwardOutcome {
 	ForwardOutcomeEndorsed,
 }
 
+/// This identifies the bucket of resources that a HTLC occupies on its outgoing channel, by its index in the
+/// bucketer's configuration.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bucket(pub u8);
+
+impl Bucket {
+	/// The bucket reserved for high reputation, endorsed HTLCs in the default protected and general configuration.
+	pub const PROTECTED: Bucket = Bucket(0);
+	/// The bucket available to any HTLC in the default protected and general configuration.
+	pub const GENERAL: Bucket = Bucket(1);
+}
+
+/// This classifies a proposed HTLC by its endorsement and the reputation of its incoming channel, which
+/// determines the bucket of resources that it may use.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HTLCClass {
+	/// The HTLC was endorsed by an incoming channel that has sufficient reputation.
+	EndorsedSufficientReputation,
+	/// The HTLC was endorsed by an incoming channel that does not have sufficient reputation.
+	EndorsedInsufficientReputation,
+	/// The HTLC was not endorsed by the incoming channel.
+	Unendorsed,
+}
+
+impl HTLCClass {
+	pub(crate) fn new(reputation_check: &ReputationCheck, incoming_endorsed: &Endorsement) -> Self {
+		if *incoming_endorsed != Endorsement::EndorsementTrue {
+			return HTLCClass::Unendorsed;
+		}
+
+		if reputation_check.sufficient_reputation() {
+			return HTLCClass::EndorsedSufficientReputation;
+		}
+
+		return HTLCClass::EndorsedInsufficientReputation;
+	}
+}
+
 /// This implements basic resource bucketing for local resource conservation.
 pub trait ResourceBucketer {
-	/// This poses a HTLC to the resource manager for addition to its appropriate bucket.
+	/// This poses a HTLC from the incoming channel provided to the resource manager for addition to the bucket
+	/// that its class is assigned to.
 	///
-	/// If there is space for the HTLC, this call will update internal state and return true.
-	/// If the bucket is full, the resource manaager will return false and its state will remain unchanged.
-	fn add_htlc(&self, protected: bool, htlc_amount_msat: u64) -> bool;
-	/// This updates the resource manager to remove an in-flight HTLC from its appropriate bucket.
+	/// If there is space for the HTLC, this call will update internal state and return the bucket that the HTLC
+	/// was added to, which may be a bucket that its assigned bucket overflows into. If there is no space, the
+	/// resource manager will return None and its state will remain unchanged.
+	fn add_htlc(&self, incoming_channel: ShortChannelId, htlc_class: HTLCClass, htlc_amount_msat: MilliSatoshi) -> Option<Bucket>;
+	/// This returns the bucket that a HTLC would be added to by [`ResourceBucketer::add_htlc`], without adding it.
+	fn available_bucket(&self, incoming_channel: ShortChannelId, htlc_class: HTLCClass, htlc_amount_msat: MilliSatoshi) -> Option<Bucket>;
+	/// This re-adds a HTLC to the bucket that it previously occupied. The bucket's limits are not enforced, as
+	/// the HTLC is already in flight and the channel may have been resized since it was added. It returns false
+	/// if the bucket does not exist.
+	fn restore_htlc(&self, incoming_channel: ShortChannelId, bucket: Bucket, htlc_amount_msat: MilliSatoshi) -> bool;
+	/// This updates the resource manager to remove an in-flight HTLC from the bucket that it was added to.
 	///
-	/// Note that this must *only* be called for HTLCs that were added with a true response.
-	fn remove_htlc(&self, protected: bool, htlc_amount_msat: u64) -> Result<bool, ErrBucketing>;
+	/// Note that this must *only* be called for HTLCs that were assigned a bucket.
+	fn remove_htlc(&self, incoming_channel: ShortChannelId, bucket: Bucket, htlc_amount_msat: MilliSatoshi) -> Result<bool, ErrBucketing>;
+	/// This returns the current usage of each of the bucketer's buckets, indexed by bucket.
+	fn occupancy(&self) -> Vec<BucketUsage>;
+	/// This recomputes the capacity of each bucket for new channel limits. HTLCs that are already in flight are
+	/// kept, so a bucket may be over its new limits and refuse new HTLCs until enough of them are resolved.
+	fn resize(&self, total_liquidity_msat: MilliSatoshi, total_slots: u64) -> Result<(), ErrBucketing>;
+}
+
+/// This reports how much of a single bucket's resources are in use.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BucketUsage {
+	/// The number of HTLC slots that are in use.
+	pub in_flight_slots: u64,
+	/// The number of HTLC slots available.
+	pub slot_limit: u64,
+	/// The amount of liquidity that is in use.
+	pub in_flight_liquidity_msat: MilliSatoshi,
+	/// The amount of liquidity available.
+	pub liquidity_limit_msat: MilliSatoshi,
 }
 
 /// This is an interface that represents the tracking of reputation for links forwarding HTLCs.
 pub trait ReputationMonitor {
 	/// This updates the reputation monitor for an incoming link to reflect that it currently has an outstanding
 	/// forwarded HTLC.
-	fn add_inflight(&mut self, proposed_htlc: ProposedHTLC, outgoing_decision: ForwardOutcome) -> Result<bool, ErrReputation>;
+	fn add_inflight(&mut self, proposed_htlc: ProposedHTLC, forward_decision: &ForwardDecision) -> Result<bool, ErrResourceManager>;
 	/// This updates the reputation monitor to resolve a previously in-flight HTLC.
-	fn resolve_inflight(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrReputation>;
-	/// This returns the details of a reputation monitor's current standing.
-	fn incoming_reputation(&mut self) -> IncomingReputation;
+	fn resolve_inflight(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrResourceManager>;
+	/// This returns the details of a reputation monitor's current standing, with the risk of in-flight HTLCs
+	/// evaluated for the blocks remaining until they expire at the current block height.
+	fn incoming_reputation(&mut self, current_height: u32) -> IncomingReputation;
 }
 
-/// This is an interface that represents the tracking of forwarding revenues for targeted outgoing links.
+/// This is an interface that represents the tracking of forwarding revenues for targeted outgoing links. As an
+/// outgoing link is shared by HTLCs from every incoming link, it is used through a shared reference so that those
+/// HTLCs don't have to wait for each other.
 pub trait TargetMonitor {
 	/// This proposes the addition of a HTLC to the outgoing channel, returning a forwarding decision for the HTLC based
 	/// on its endorsment and the reputation of the incoming link.
-	fn add_inflight(&mut self, incoming_reputation: IncomingReputation, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ()>;
+	fn add_inflight(&self, incoming_reputation: IncomingReputation, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager>;
 	/// This removes a HTLC from the outgoing channel.
-	fn resolve_inflight(&mut self, resolved_htlc: ResolvedHTLC, in_flight_htlc: InFlightHTLC) -> Result<bool, ()>;
+	fn resolve_inflight(&self, resolved_htlc: ResolvedHTLC, in_flight_htlc: InFlightHTLC) -> Result<bool, ErrResourceManager>;
 }
 
 /// This represents the endorsment signaling that is passed along with a HTLC.
-#[derive(Clone, PartialEq)]
-pub(crate) enum Endorsement {
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Endorsement {
 	/// This indicates that the TLV was not present.
 	EndorsementNone,
 	/// This indicates that the TLV was present with a zero value.
@@ -116,7 +228,7 @@ pub(crate) enum Endorsement {
 }
 
 impl Endorsement {
-	fn new_endorsement_signal(endorse: bool) -> Self {
+	pub fn new_endorsement_signal(endorse: bool) -> Self {
 		if endorse {
 			return Endorsement::EndorsementTrue;
 		}
@@ -126,60 +238,261 @@ impl Endorsement {
 }
 
 /// This provides information about a HTLC has been locked in on our incoming channel, but not yet forwarded.
-#[derive(Clone)]
-pub(crate) struct ProposedHTLC {
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProposedHTLC {
 	/// The channel that has sent this HTLC to the local node for forwarding.
-	pub(crate) incoming_channel: u64,
+	pub(crate) incoming_channel: ShortChannelId,
 	/// This is the outgoing channel that the sending node has requested.
-	pub(crate) outgoing_channel: u64,
+	pub(crate) outgoing_channel: ShortChannelId,
 	/// This is the HTLC index on the incoming channel.
 	pub(crate) incoming_index: u32,
 	/// This indicates whether the incoming channel forwarded this HTLC as endorsed.
 	pub(crate) incoming_endorsed: Endorsement,
 	/// This is the amount of the HTLC on the incoming channel.
-	incoming_amount_msat: u64,
+	pub(crate) incoming_amount_msat: MilliSatoshi,
 	/// This is the amount of the HTLC on the outgoing channel.
-	pub(crate) outgoing_amount_msat: u64,
+	pub(crate) outgoing_amount_msat: MilliSatoshi,
 	/// This is difference between the block height at which the HTLC was forwarded
 	/// and its outgoing CLTV expiry.
 	pub(crate) cltv_expiry_delta: u32,
+	/// This is the absolute block height at which the incoming HTLC expires.
+	pub(crate) cltv_expiry: u32,
 }
 
 impl ProposedHTLC {
-	pub(crate) fn forwarding_fee(&self) -> u64 {
-		return self.incoming_amount_msat - self.outgoing_amount_msat;
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(incoming_channel: ShortChannelId, outgoing_channel: ShortChannelId, incoming_index: u32, incoming_endorsed: Endorsement,
+		incoming_amount_msat: MilliSatoshi, outgoing_amount_msat: MilliSatoshi, cltv_expiry_delta: u32, cltv_expiry: u32) -> Self {
+		return ProposedHTLC {
+			incoming_channel,
+			outgoing_channel,
+			incoming_index,
+			incoming_endorsed,
+			incoming_amount_msat,
+			outgoing_amount_msat,
+			cltv_expiry_delta,
+			cltv_expiry,
+		};
+	}
+
+	/// Returns the channel that sent the HTLC to us for forwarding.
+	pub fn incoming_channel(&self) -> ShortChannelId {
+		return self.incoming_channel;
+	}
+
+	/// Returns the outgoing channel that the sending node requested.
+	pub fn outgoing_channel(&self) -> ShortChannelId {
+		return self.outgoing_channel;
+	}
+
+	/// Returns the HTLC's index on the incoming channel.
+	pub fn incoming_index(&self) -> u32 {
+		return self.incoming_index;
 	}
+
+	/// Returns whether the incoming channel forwarded the HTLC as endorsed.
+	pub fn incoming_endorsed(&self) -> Endorsement {
+		return self.incoming_endorsed.clone();
+	}
+
+	/// Returns the amount of the HTLC on the incoming channel.
+	pub fn incoming_amount_msat(&self) -> MilliSatoshi {
+		return self.incoming_amount_msat;
+	}
+
+	/// Returns the amount of the HTLC on the outgoing channel.
+	pub fn outgoing_amount_msat(&self) -> MilliSatoshi {
+		return self.outgoing_amount_msat;
+	}
+
+	/// Returns the difference between the block height at which the HTLC was forwarded and its outgoing CLTV
+	/// expiry.
+	pub fn cltv_expiry_delta(&self) -> u32 {
+		return self.cltv_expiry_delta;
+	}
+
+	/// Returns the absolute block height at which the incoming HTLC expires.
+	pub fn cltv_expiry(&self) -> u32 {
+		return self.cltv_expiry;
+	}
+
+	/// Returns the fee offered for forwarding the HTLC, or None if its outgoing amount is greater than its
+	/// incoming amount.
+	pub fn forwarding_fee(&self) -> Option<MilliSatoshi> {
+		return self.incoming_amount_msat.checked_sub(self.outgoing_amount_msat);
+	}
+
+	/// Returns the HTLC's forwarding fee in millisatoshis. The manager rejects HTLCs with a negative fee, so the
+	/// fee of any HTLC that it tracks is known to be valid.
+	pub(crate) fn fee_msat(&self) -> u64 {
+		return self.forwarding_fee().unwrap_or(MilliSatoshi::ZERO).to_msat();
+	}
+
+	/// Returns the incoming side of the HTLC's circuit.
+	pub fn incoming_circuit(&self) -> CircuitKey {
+		return CircuitKey { short_channel_id: self.incoming_channel, htlc_index: self.incoming_index };
+	}
+}
+
+/// This identifies a HTLC on one side of its circuit through our node.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CircuitKey {
+	/// The short channel ID of the channel that the HTLC was added to.
+	pub short_channel_id: ShortChannelId,
+	/// The index of the HTLC on the channel.
+	pub htlc_index: u32,
 }
 
 /// This tracks a HTLC forward that is currently in flight.
-#[derive(Clone)]
-pub(crate) struct InFlightHTLC {
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InFlightHTLC {
 	/// This is the time at which the incoming HTLC was added to the incoming channel.
-	pub(crate) timestamp_added: Instant,
+	pub(crate) timestamp_added: SystemTime,
 	/// This indicates what resource allocation was assigned to the outgoing HTLC.
 	pub(crate) outgoing_decision: ForwardOutcome,
+	/// This is the bucket that the HTLC occupies on the outgoing channel, if it was given resources.
+	pub(crate) bucket: Option<Bucket>,
+	/// This is the absolute block height at which the HTLC expires, or None if it was restored from a snapshot
+	/// that did not record it and the block height has not been updated since.
+	pub(crate) expiry_height: Option<u32>,
+	/// This is the HTLC index on the outgoing channel, once it has been assigned.
+	pub(crate) outgoing_index: Option<u32>,
 	/// This contains the original details of the HTLC that was forwarded to us.
 	pub(crate) proposed_htlc: ProposedHTLC,
 }
 
+impl InFlightHTLC {
+	/// Creates a HTLC that is known to be in flight, for reconciliation with the manager's state. The HTLC is
+	/// assigned a bucket by the manager.
+	pub fn new(timestamp_added: SystemTime, outgoing_decision: ForwardOutcome, expiry_height: u32, outgoing_index: Option<u32>,
+		proposed_htlc: ProposedHTLC) -> Self {
+		return InFlightHTLC {
+			timestamp_added,
+			outgoing_decision,
+			bucket: None,
+			expiry_height: Some(expiry_height),
+			outgoing_index,
+			proposed_htlc,
+		};
+	}
+
+	/// Returns the time at which the incoming HTLC was added to the incoming channel.
+	pub fn timestamp_added(&self) -> SystemTime {
+		return self.timestamp_added;
+	}
+
+	/// Returns the resource allocation that was assigned to the outgoing HTLC.
+	pub fn outgoing_decision(&self) -> ForwardOutcome {
+		return self.outgoing_decision.clone();
+	}
+
+	/// Returns the bucket that the HTLC occupies on the outgoing channel, if it was given resources.
+	pub fn bucket(&self) -> Option<Bucket> {
+		return self.bucket;
+	}
+
+	/// Returns the absolute block height at which the HTLC expires, or None if it was restored from a snapshot
+	/// that did not record it and the block height has not been updated since.
+	pub fn expiry_height(&self) -> Option<u32> {
+		return self.expiry_height;
+	}
+
+	/// Returns the HTLC's index on the outgoing channel, once it has been assigned.
+	pub fn outgoing_index(&self) -> Option<u32> {
+		return self.outgoing_index;
+	}
+
+	/// Returns the original details of the HTLC that was forwarded to us.
+	pub fn proposed_htlc(&self) -> &ProposedHTLC {
+		return &self.proposed_htlc;
+	}
+
+	/// Returns the incoming side of the HTLC's circuit.
+	pub fn incoming_circuit(&self) -> CircuitKey {
+		return self.proposed_htlc.incoming_circuit();
+	}
+
+	/// Returns the outgoing side of the HTLC's circuit, if its outgoing index has been assigned.
+	pub fn outgoing_circuit(&self) -> Option<CircuitKey> {
+		return self.outgoing_index.map(|htlc_index| CircuitKey {
+			short_channel_id: self.proposed_htlc.outgoing_channel,
+			htlc_index,
+		});
+	}
+}
+
 /// This summarizes the resolution of an in-flight HTLC.
-#[derive(Clone)]
-pub(crate) struct ResolvedHTLC {
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResolvedHTLC {
 	/// This is the time at which a HTLC was resolved.
-	pub(crate) timestamp_settled: Instant,
-	/// This is the short channel ID of the channel that originally forwarded the incoming HTLC.
+	pub(crate) timestamp_settled: SystemTime,
+	/// This is the HTLC ID on the incoming link.
 	pub(crate) incoming_index: u32,
+	/// This is the short channel ID of the channel that originally forwarded the incoming HTLC.
+	pub(crate) incoming_channel: ShortChannelId,
 	/// This is the HTLC ID on the outgoing link. Note that HTLCs that fail locally won't have this value assigned.
-	pub(crate) incoming_channel: u64,
-	/// RThis is the HTLC ID on the outgoing link. Note that HTLCs that fail locally won't have this value assigned.
-	outgoing_index: u32,
+	pub(crate) outgoing_index: u32,
 	/// This is the short channel ID of the channel that forwarded the outgoing HTLC.
-	pub(crate) outgoing_channel: u64,
+	pub(crate) outgoing_channel: ShortChannelId,
 	/// This is true if the HTLC was fulfilled.
 	pub(crate) success: bool,
 }
 
+impl ResolvedHTLC {
+	pub fn new(timestamp_settled: SystemTime, incoming_channel: ShortChannelId, incoming_index: u32, outgoing_channel: ShortChannelId,
+		outgoing_index: u32, success: bool) -> Self {
+		return ResolvedHTLC {
+			timestamp_settled,
+			incoming_index,
+			incoming_channel,
+			outgoing_index,
+			outgoing_channel,
+			success,
+		};
+	}
+
+	/// Returns the time at which the HTLC was resolved.
+	pub fn timestamp_settled(&self) -> SystemTime {
+		return self.timestamp_settled;
+	}
+
+	/// Returns the channel that originally forwarded the incoming HTLC.
+	pub fn incoming_channel(&self) -> ShortChannelId {
+		return self.incoming_channel;
+	}
+
+	/// Returns the HTLC's index on the incoming channel.
+	pub fn incoming_index(&self) -> u32 {
+		return self.incoming_index;
+	}
+
+	/// Returns the channel that forwarded the outgoing HTLC.
+	pub fn outgoing_channel(&self) -> ShortChannelId {
+		return self.outgoing_channel;
+	}
+
+	/// Returns the HTLC's index on the outgoing channel. HTLCs that fail locally won't have this value assigned.
+	pub fn outgoing_index(&self) -> u32 {
+		return self.outgoing_index;
+	}
+
+	/// Returns true if the HTLC was fulfilled.
+	pub fn success(&self) -> bool {
+		return self.success;
+	}
+
+	/// Returns the incoming side of the resolved HTLC's circuit.
+	pub fn incoming_circuit(&self) -> CircuitKey {
+		return CircuitKey { short_channel_id: self.incoming_channel, htlc_index: self.incoming_index };
+	}
+}
+
 /// This represents a HTLC that our node has previously forwarded.
+#[allow(dead_code)]
 struct ForwardedHTLC {
 	/// This contains the original forwarding details of the HTLC.
 	in_flight_htlc: InFlightHTLC,
@@ -187,10 +500,153 @@ struct ForwardedHTLC {
 	resolution: ResolvedHTLC,
 }
 
+/// This indicates which roles a channel may take in a forward.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelDirection {
+	/// The channel only sends HTLCs to us for forwarding, so we only track its reputation.
+	Incoming,
+	/// The channel is only used to forward HTLCs out, so we only track its revenue and resources.
+	Outgoing,
+	/// The channel may be used on either side of a forward.
+	Bidirectional,
+}
+
+impl ChannelDirection {
+	pub(crate) fn incoming(&self) -> bool {
+		return *self != ChannelDirection::Outgoing;
+	}
+
+	pub(crate) fn outgoing(&self) -> bool {
+		return *self != ChannelDirection::Incoming;
+	}
+}
+
 /// This provides information about a channel's routing restrictions.
-pub(crate) struct ChannelInfo {
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelInfo {
 	/// Total number of HTLCs allowed in-flight.
-	pub(crate) in_flight_htlc_limit: u64,
+	pub in_flight_htlc_limit: u64,
 	/// Total amouhnt of liquidity allowed in-flight.
-	pub(crate) in_flight_liquidity_limit: u64,
+	pub in_flight_liquidity_limit: MilliSatoshi,
+	/// The roles that the channel may take in a forward.
+	pub direction: ChannelDirection,
+	/// Overrides the manager's revenue window for the channel's outgoing revenue, if set.
+	pub revenue_window: Option<Duration>,
+	/// Overrides the manager's reputation window for the channel's incoming reputation, if set.
+	pub reputation_window: Option<Duration>,
+	/// The node that the channel is with, if known. When the manager aggregates reputation by peer, the
+	/// channel shares its incoming reputation with all other channels to the same node.
+	pub peer: Option<NodeId>,
+}
+
+impl ChannelInfo {
+	/// Creates the routing restrictions for a channel that may be used in both directions.
+	pub fn new(in_flight_htlc_limit: u64, in_flight_liquidity_limit: MilliSatoshi) -> Self {
+		return ChannelInfo {
+			in_flight_htlc_limit,
+			in_flight_liquidity_limit,
+			direction: ChannelDirection::Bidirectional,
+			revenue_window: None,
+			reputation_window: None,
+			peer: None,
+		};
+	}
+}
+
+/// This is the public key of a node in the network, in compressed form.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct NodeId(pub [u8; 33]);
+
+impl fmt::Display for NodeId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for byte in self.0.iter() {
+			write!(f, "{:02x}", byte)?;
+		}
+		return Ok(());
+	}
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for NodeId {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		return serializer.serialize_bytes(&self.0);
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NodeId {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let bytes = Vec::<u8>::deserialize(deserializer)?;
+		let len = bytes.len();
+		return bytes.try_into().map(NodeId).map_err(|_| serde::de::Error::invalid_length(len, &"33 bytes"));
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_htlc_accessors() {
+		let proposed_htlc = ProposedHTLC::new(ShortChannelId(1), ShortChannelId(2), 3, Endorsement::EndorsementTrue,
+			MilliSatoshi(11_000), MilliSatoshi(10_000), 40, 800_040);
+		assert_eq!(proposed_htlc.incoming_channel(), ShortChannelId(1));
+		assert_eq!(proposed_htlc.outgoing_channel(), ShortChannelId(2));
+		assert_eq!(proposed_htlc.incoming_index(), 3);
+		assert_eq!(proposed_htlc.incoming_endorsed(), Endorsement::EndorsementTrue);
+		assert_eq!(proposed_htlc.incoming_amount_msat(), MilliSatoshi(11_000));
+		assert_eq!(proposed_htlc.outgoing_amount_msat(), MilliSatoshi(10_000));
+		assert_eq!(proposed_htlc.cltv_expiry_delta(), 40);
+		assert_eq!(proposed_htlc.cltv_expiry(), 800_040);
+
+		let timestamp_added = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+		let in_flight_htlc = InFlightHTLC {
+			bucket: Some(Bucket::PROTECTED),
+			..InFlightHTLC::new(timestamp_added, ForwardOutcome::ForwardOutcomeEndorsed, 800_040, Some(5), proposed_htlc)
+		};
+		assert_eq!(in_flight_htlc.timestamp_added(), timestamp_added);
+		assert_eq!(in_flight_htlc.outgoing_decision(), ForwardOutcome::ForwardOutcomeEndorsed);
+		assert_eq!(in_flight_htlc.bucket(), Some(Bucket::PROTECTED));
+		assert_eq!(in_flight_htlc.expiry_height(), Some(800_040));
+		assert_eq!(in_flight_htlc.outgoing_index(), Some(5));
+		assert_eq!(in_flight_htlc.proposed_htlc().incoming_index(), 3);
+
+		let resolved_htlc = ResolvedHTLC::new(timestamp_added, ShortChannelId(1), 3, ShortChannelId(2), 5, true);
+		assert_eq!(resolved_htlc.timestamp_settled(), timestamp_added);
+		assert_eq!(resolved_htlc.incoming_channel(), ShortChannelId(1));
+		assert_eq!(resolved_htlc.incoming_index(), 3);
+		assert_eq!(resolved_htlc.outgoing_channel(), ShortChannelId(2));
+		assert_eq!(resolved_htlc.outgoing_index(), 5);
+		assert_eq!(resolved_htlc.success(), true);
+	}
+
+	#[test]
+	#[cfg(feature = "serde")]
+	fn test_serde_round_trip() {
+		let in_flight_htlc = InFlightHTLC {
+			timestamp_added: SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000),
+			outgoing_decision: ForwardOutcome::ForwardOutcomeEndorsed,
+			bucket: Some(Bucket::PROTECTED),
+			expiry_height: Some(800_040),
+			outgoing_index: None,
+			proposed_htlc: ProposedHTLC::new(ShortChannelId(1), ShortChannelId(2), 3, Endorsement::EndorsementTrue,
+				MilliSatoshi(11_000), MilliSatoshi(10_000), 40, 800_040),
+		};
+
+		let encoded = serde_json::to_string(&in_flight_htlc).unwrap();
+		let decoded: InFlightHTLC = serde_json::from_str(&encoded).unwrap();
+		assert_eq!(decoded.timestamp_added, in_flight_htlc.timestamp_added);
+		assert_eq!(decoded.outgoing_decision, ForwardOutcome::ForwardOutcomeEndorsed);
+		assert_eq!(decoded.bucket, Some(Bucket::PROTECTED));
+		assert_eq!(decoded.proposed_htlc.incoming_endorsed, Endorsement::EndorsementTrue);
+		assert_eq!(decoded.proposed_htlc.forwarding_fee(), Some(MilliSatoshi(1_000)));
+
+		let chan_info = ChannelInfo { direction: ChannelDirection::Outgoing, ..ChannelInfo::new(483, MilliSatoshi(1_000_000)) };
+		let decoded: ChannelInfo = serde_json::from_str(&serde_json::to_string(&chan_info).unwrap()).unwrap();
+		assert_eq!(decoded.direction, ChannelDirection::Outgoing);
+		assert_eq!(decoded.in_flight_htlc_limit, 483);
+	}
 }
@@ -1,6 +1,14 @@
+pub mod atomic_resource_bucketing;
+pub mod audit;
+pub mod clock;
 pub mod interface;
+pub mod persistence;
 pub mod resource_manager;
+pub mod shared_resource_manager;
 pub mod reputation_tracker;
 pub mod decaying_average;
+pub mod error;
 pub mod resource_bucketing;
 pub mod target_tracker;
+pub mod types;
+pub mod wire;
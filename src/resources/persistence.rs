@@ -0,0 +1,994 @@
+use core::time::Duration;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::resources::decaying_average::DecayingAverageStart;
+use crate::resources::interface::{Bucket, ChannelDirection, ChannelInfo, Endorsement, ForwardOutcome, InFlightHTLC, NodeId, ProposedHTLC};
+use crate::resources::resource_manager::{BucketerType, ChannelArchive, EndorsementPolicy, ManagerConfig};
+use crate::resources::resource_bucketing::{BucketConfig, BucketTier};
+use crate::resources::types::{MilliSatoshi, ShortChannelId};
+
+/// The version of the snapshot encoding written by this crate.
+const SNAPSHOT_VERSION: u8 = 3;
+
+/// The snapshot versions that can be read. Fields that were added after a snapshot was written are given
+/// their default values. Version 2 was written by development builds whose layouts differed from one another,
+/// so it can't be read reliably and is rejected.
+const READABLE_SNAPSHOT_VERSIONS: [u8; 2] = [1, SNAPSHOT_VERSION];
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ErrPersistence {
+	Io(io::ErrorKind),
+	UnknownVersion(u8),
+	InvalidValue,
+}
+
+impl From<io::Error> for ErrPersistence {
+	fn from(e: io::Error) -> Self {
+		return ErrPersistence::Io(e.kind());
+	}
+}
+
+/// A type that can be encoded to a byte stream.
+pub trait Writeable {
+	/// Writes the big-endian encoding of the type to the writer provided.
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error>;
+}
+
+/// A type that can be decoded from a byte stream written by its `Writeable` implementation.
+pub trait Readable: Sized {
+	/// Reads the type from the reader provided.
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence>;
+}
+
+/// A type whose encoding has changed between snapshot versions, so that it must be read according to the
+/// version of the snapshot that contains it. Its `Readable` implementation reads the current version.
+pub(crate) trait VersionedReadable: Sized {
+	/// Reads the type as it was encoded by the snapshot version provided.
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence>;
+}
+
+/// The persisted state of an incoming channel's reputation.
+#[derive(Clone, Debug)]
+pub(crate) struct ReputationSnapshot {
+	/// The channel's reputation as of its last update.
+	pub(crate) revenue: DecayingAverageStart,
+	/// The HTLCs that the channel had in flight.
+	pub(crate) in_flight_htlcs: Vec<InFlightHTLC>,
+}
+
+/// The persisted state of a single channel registered with the manager.
+#[derive(Clone, Debug)]
+pub(crate) struct ChannelSnapshot {
+	pub(crate) short_channel_id: ShortChannelId,
+	/// The routing restrictions that the channel was registered with.
+	pub(crate) chan_info: ChannelInfo,
+	/// Present if the channel is tracked as an incoming channel.
+	pub(crate) reputation: Option<ReputationSnapshot>,
+	/// Present if the channel is tracked as an outgoing channel.
+	pub(crate) target_revenue: Option<DecayingAverageStart>,
+}
+
+/// The persisted reputation of a peer, shared by all of its channels.
+#[derive(Clone, Debug)]
+pub(crate) struct PeerSnapshot {
+	pub(crate) node_id: NodeId,
+	pub(crate) reputation: ReputationSnapshot,
+}
+
+/// A point-in-time copy of a `ResourceManager`'s state, which can be written to disk and used
+/// to restore the manager after a restart.
+#[derive(Clone, Debug)]
+pub struct ResourceManagerSnapshot {
+	pub(crate) manager_config: ManagerConfig,
+	pub(crate) bucket_config: BucketConfig,
+	pub(crate) block_height: u32,
+	pub(crate) channels: Vec<ChannelSnapshot>,
+	pub(crate) peers: Vec<PeerSnapshot>,
+	/// The additional short channel ids that channels are known by, paired with the id of their channel.
+	pub(crate) channel_aliases: Vec<(ShortChannelId, ShortChannelId)>,
+	pub(crate) archived_channels: Vec<ChannelArchive>,
+}
+
+impl ResourceManagerSnapshot {
+	/// Writes the snapshot to the path provided. The snapshot is first written to a temporary file that
+	/// replaces any existing file once complete, so a crash part way through will not corrupt prior state.
+	pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ErrPersistence> {
+		let path = path.as_ref();
+		let mut tmp_path = path.as_os_str().to_owned();
+		tmp_path.push(".tmp");
+
+		{
+			let mut writer = BufWriter::new(File::create(&tmp_path)?);
+			self.write(&mut writer)?;
+			writer.flush()?;
+			writer.get_ref().sync_all()?;
+		}
+
+		fs::rename(&tmp_path, path)?;
+
+		return Ok(());
+	}
+
+	/// Reads a snapshot that was previously written with `write_to_file`.
+	pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ErrPersistence> {
+		let mut reader = BufReader::new(File::open(path)?);
+		return Self::read(&mut reader);
+	}
+}
+
+impl Writeable for u8 {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return writer.write_all(&[*self]);
+	}
+}
+
+impl Readable for u8 {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let mut buf = [0; 1];
+		reader.read_exact(&mut buf)?;
+		return Ok(buf[0]);
+	}
+}
+
+impl Writeable for u32 {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return writer.write_all(&self.to_be_bytes());
+	}
+}
+
+impl Readable for u32 {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let mut buf = [0; 4];
+		reader.read_exact(&mut buf)?;
+		return Ok(u32::from_be_bytes(buf));
+	}
+}
+
+impl Writeable for u64 {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return writer.write_all(&self.to_be_bytes());
+	}
+}
+
+impl Readable for u64 {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let mut buf = [0; 8];
+		reader.read_exact(&mut buf)?;
+		return Ok(u64::from_be_bytes(buf));
+	}
+}
+
+impl Writeable for f64 {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return self.to_bits().write(writer);
+	}
+}
+
+impl Readable for f64 {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(f64::from_bits(u64::read(reader)?));
+	}
+}
+
+impl Writeable for ShortChannelId {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return self.0.write(writer);
+	}
+}
+
+impl Readable for ShortChannelId {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(ShortChannelId(u64::read(reader)?));
+	}
+}
+
+impl Writeable for MilliSatoshi {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return self.0.write(writer);
+	}
+}
+
+impl Readable for MilliSatoshi {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(MilliSatoshi(u64::read(reader)?));
+	}
+}
+
+impl Writeable for NodeId {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return writer.write_all(&self.0);
+	}
+}
+
+impl Readable for NodeId {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let mut buf = [0; 33];
+		reader.read_exact(&mut buf)?;
+		return Ok(NodeId(buf));
+	}
+}
+
+impl Writeable for bool {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return (*self as u8).write(writer);
+	}
+}
+
+impl Readable for bool {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		match u8::read(reader)? {
+			0 => return Ok(false),
+			1 => return Ok(true),
+			_ => return Err(ErrPersistence::InvalidValue),
+		}
+	}
+}
+
+impl Writeable for Duration {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.as_secs().write(writer)?;
+		return self.subsec_nanos().write(writer);
+	}
+}
+
+impl Readable for Duration {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let secs = u64::read(reader)?;
+		let nanos = u32::read(reader)?;
+		if nanos >= 1_000_000_000 {
+			return Err(ErrPersistence::InvalidValue);
+		}
+		return Ok(Duration::new(secs, nanos));
+	}
+}
+
+/// Timestamps are written as the duration since the unix epoch, so that they remain meaningful across restarts.
+impl Writeable for SystemTime {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let since_epoch = self.duration_since(SystemTime::UNIX_EPOCH)
+			.map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "timestamp before unix epoch"))?;
+		return since_epoch.write(writer);
+	}
+}
+
+impl Readable for SystemTime {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return SystemTime::UNIX_EPOCH.checked_add(Duration::read(reader)?).ok_or(ErrPersistence::InvalidValue);
+	}
+}
+
+impl<T: Writeable> Writeable for Option<T> {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		match self {
+			Some(value) => {
+				true.write(writer)?;
+				return value.write(writer);
+			},
+			None => return false.write(writer),
+		}
+	}
+}
+
+impl<T: Readable> Readable for Option<T> {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		if bool::read(reader)? {
+			return Ok(Some(T::read(reader)?));
+		}
+		return Ok(None);
+	}
+}
+
+impl<T: Writeable> Writeable for Vec<T> {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		(self.len() as u64).write(writer)?;
+		for item in self.iter() {
+			item.write(writer)?;
+		}
+		return Ok(());
+	}
+}
+
+impl<T: Readable> Readable for Vec<T> {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let len = u64::read(reader)?;
+		// Items are pushed individually rather than pre-allocated so that a corrupt length can't exhaust memory.
+		let mut items = Vec::new();
+		for _ in 0..len {
+			items.push(T::read(reader)?);
+		}
+		return Ok(items);
+	}
+}
+
+impl<T: VersionedReadable> VersionedReadable for Option<T> {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		if bool::read(reader)? {
+			return Ok(Some(T::read_version(reader, version)?));
+		}
+		return Ok(None);
+	}
+}
+
+impl<T: VersionedReadable> VersionedReadable for Vec<T> {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		let len = u64::read(reader)?;
+		let mut items = Vec::new();
+		for _ in 0..len {
+			items.push(T::read_version(reader, version)?);
+		}
+		return Ok(items);
+	}
+}
+
+impl<A: Writeable, B: Writeable> Writeable for (A, B) {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.0.write(writer)?;
+		return self.1.write(writer);
+	}
+}
+
+impl<A: Readable, B: Readable> Readable for (A, B) {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok((A::read(reader)?, B::read(reader)?));
+	}
+}
+
+impl Writeable for DecayingAverageStart {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.last_update.write(writer)?;
+		return self.value.write(writer);
+	}
+}
+
+impl Readable for DecayingAverageStart {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(DecayingAverageStart {
+			last_update: SystemTime::read(reader)?,
+			value: f64::read(reader)?,
+		});
+	}
+}
+
+impl Writeable for ManagerConfig {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.revenue_window.write(writer)?;
+		self.reputation_multiplier.write(writer)?;
+		self.protected_percentage.write(writer)?;
+		self.resolution_period.write(writer)?;
+		self.block_time.write(writer)?;
+		self.aggregate_peer_reputation.write(writer)?;
+		self.endorsement_policy.write(writer)?;
+		return self.bucketer.write(writer);
+	}
+}
+
+impl Readable for ManagerConfig {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Self::read_version(reader, SNAPSHOT_VERSION);
+	}
+}
+
+impl VersionedReadable for ManagerConfig {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		let manager_config = ManagerConfig {
+			revenue_window: Duration::read(reader)?,
+			reputation_multiplier: u8::read(reader)?,
+			protected_percentage: u64::read(reader)?,
+			resolution_period: Duration::read(reader)?,
+			block_time: Duration::read(reader)?,
+			..ManagerConfig::default()
+		};
+		if version < 3 {
+			return Ok(manager_config);
+		}
+
+		return Ok(ManagerConfig {
+			aggregate_peer_reputation: bool::read(reader)?,
+			endorsement_policy: EndorsementPolicy::read(reader)?,
+			bucketer: BucketerType::read(reader)?,
+			..manager_config
+		});
+	}
+}
+
+impl Writeable for EndorsementPolicy {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let policy: u8 = match self {
+			EndorsementPolicy::RelayAsReceived => 0,
+			EndorsementPolicy::EndorseIfProtected => 1,
+			EndorsementPolicy::NeverEndorse => 2,
+		};
+		return policy.write(writer);
+	}
+}
+
+impl Readable for EndorsementPolicy {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		match u8::read(reader)? {
+			0 => return Ok(EndorsementPolicy::RelayAsReceived),
+			1 => return Ok(EndorsementPolicy::EndorseIfProtected),
+			2 => return Ok(EndorsementPolicy::NeverEndorse),
+			_ => return Err(ErrPersistence::InvalidValue),
+		}
+	}
+}
+
+impl Writeable for BucketerType {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let bucketer: u8 = match self {
+			BucketerType::Locking => 0,
+			BucketerType::Atomic => 1,
+		};
+		return bucketer.write(writer);
+	}
+}
+
+impl Readable for BucketerType {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		match u8::read(reader)? {
+			0 => return Ok(BucketerType::Locking),
+			1 => return Ok(BucketerType::Atomic),
+			_ => return Err(ErrPersistence::InvalidValue),
+		}
+	}
+}
+
+impl Writeable for ChannelInfo {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.in_flight_htlc_limit.write(writer)?;
+		self.in_flight_liquidity_limit.write(writer)?;
+		let direction: u8 = match self.direction {
+			ChannelDirection::Incoming => 0,
+			ChannelDirection::Outgoing => 1,
+			ChannelDirection::Bidirectional => 2,
+		};
+		direction.write(writer)?;
+		self.revenue_window.write(writer)?;
+		self.reputation_window.write(writer)?;
+		return self.peer.write(writer);
+	}
+}
+
+impl Readable for ChannelInfo {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Self::read_version(reader, SNAPSHOT_VERSION);
+	}
+}
+
+impl VersionedReadable for ChannelInfo {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		let in_flight_htlc_limit = u64::read(reader)?;
+		let in_flight_liquidity_limit = MilliSatoshi::read(reader)?;
+		let direction = match u8::read(reader)? {
+			0 => ChannelDirection::Incoming,
+			1 => ChannelDirection::Outgoing,
+			2 => ChannelDirection::Bidirectional,
+			_ => return Err(ErrPersistence::InvalidValue),
+		};
+		let chan_info = ChannelInfo {
+			direction,
+			..ChannelInfo::new(in_flight_htlc_limit, in_flight_liquidity_limit)
+		};
+		if version < 3 {
+			return Ok(chan_info);
+		}
+
+		return Ok(ChannelInfo {
+			revenue_window: Option::read(reader)?,
+			reputation_window: Option::read(reader)?,
+			peer: Option::read(reader)?,
+			..chan_info
+		});
+	}
+}
+
+impl Writeable for Endorsement {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let endorsement: u8 = match self {
+			Endorsement::EndorsementNone => 0,
+			Endorsement::EndorsementFalse => 1,
+			Endorsement::EndorsementTrue => 2,
+		};
+		return endorsement.write(writer);
+	}
+}
+
+impl Readable for Endorsement {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		match u8::read(reader)? {
+			0 => return Ok(Endorsement::EndorsementNone),
+			1 => return Ok(Endorsement::EndorsementFalse),
+			2 => return Ok(Endorsement::EndorsementTrue),
+			_ => return Err(ErrPersistence::InvalidValue),
+		}
+	}
+}
+
+impl Writeable for ForwardOutcome {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		let outcome: u8 = match self {
+			ForwardOutcome::ForwardOutcomeNoResources => 0,
+			ForwardOutcome::ForwardOutcomeUnendorsed => 1,
+			ForwardOutcome::ForwardOutcomeEndorsed => 2,
+		};
+		return outcome.write(writer);
+	}
+}
+
+impl Readable for ForwardOutcome {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		match u8::read(reader)? {
+			0 => return Ok(ForwardOutcome::ForwardOutcomeNoResources),
+			1 => return Ok(ForwardOutcome::ForwardOutcomeUnendorsed),
+			2 => return Ok(ForwardOutcome::ForwardOutcomeEndorsed),
+			_ => return Err(ErrPersistence::InvalidValue),
+		}
+	}
+}
+
+impl Writeable for Bucket {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		return self.0.write(writer);
+	}
+}
+
+impl Readable for Bucket {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(Bucket(u8::read(reader)?));
+	}
+}
+
+impl Writeable for BucketTier {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.slot_percentage.write(writer)?;
+		self.liquidity_percentage.write(writer)?;
+		self.overflow.write(writer)?;
+		return self.fair_share_percentage.write(writer);
+	}
+}
+
+impl Readable for BucketTier {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(BucketTier {
+			slot_percentage: u64::read(reader)?,
+			liquidity_percentage: u64::read(reader)?,
+			overflow: Option::read(reader)?,
+			fair_share_percentage: Option::read(reader)?,
+		});
+	}
+}
+
+impl Writeable for BucketConfig {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.buckets.write(writer)?;
+		self.endorsed_sufficient_reputation.write(writer)?;
+		self.endorsed_insufficient_reputation.write(writer)?;
+		return self.unendorsed.write(writer);
+	}
+}
+
+impl Readable for BucketConfig {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(BucketConfig {
+			buckets: Vec::read(reader)?,
+			endorsed_sufficient_reputation: Bucket::read(reader)?,
+			endorsed_insufficient_reputation: Bucket::read(reader)?,
+			unendorsed: Bucket::read(reader)?,
+		});
+	}
+}
+
+impl Writeable for ProposedHTLC {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.incoming_channel.write(writer)?;
+		self.outgoing_channel.write(writer)?;
+		self.incoming_index.write(writer)?;
+		self.incoming_endorsed.write(writer)?;
+		self.incoming_amount_msat.write(writer)?;
+		self.outgoing_amount_msat.write(writer)?;
+		self.cltv_expiry_delta.write(writer)?;
+		return self.cltv_expiry.write(writer);
+	}
+}
+
+impl Readable for ProposedHTLC {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Self::read_version(reader, SNAPSHOT_VERSION);
+	}
+}
+
+impl VersionedReadable for ProposedHTLC {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		let proposed_htlc = ProposedHTLC {
+			incoming_channel: ShortChannelId::read(reader)?,
+			outgoing_channel: ShortChannelId::read(reader)?,
+			incoming_index: u32::read(reader)?,
+			incoming_endorsed: Endorsement::read(reader)?,
+			incoming_amount_msat: MilliSatoshi::read(reader)?,
+			outgoing_amount_msat: MilliSatoshi::read(reader)?,
+			cltv_expiry_delta: u32::read(reader)?,
+			cltv_expiry: 0,
+		};
+
+		// Version 1 snapshots did not record the HTLC's absolute expiry, which is set once the manager is given
+		// the current block height.
+		let cltv_expiry = match version {
+			1 => 0,
+			_ => u32::read(reader)?,
+		};
+
+		return Ok(ProposedHTLC { cltv_expiry, ..proposed_htlc });
+	}
+}
+
+impl Writeable for InFlightHTLC {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.timestamp_added.write(writer)?;
+		self.outgoing_decision.write(writer)?;
+		self.bucket.write(writer)?;
+		self.expiry_height.write(writer)?;
+		self.outgoing_index.write(writer)?;
+		return self.proposed_htlc.write(writer);
+	}
+}
+
+impl Readable for InFlightHTLC {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Self::read_version(reader, SNAPSHOT_VERSION);
+	}
+}
+
+impl VersionedReadable for InFlightHTLC {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		if version < 3 {
+			let timestamp_added = SystemTime::read(reader)?;
+			let outgoing_decision = ForwardOutcome::read(reader)?;
+			let proposed_htlc = ProposedHTLC::read_version(reader, version)?;
+
+			// Version 1 snapshots always used the default protected and general buckets, and did not record the
+			// HTLC's expiry height.
+			let bucket = match outgoing_decision {
+				ForwardOutcome::ForwardOutcomeNoResources => None,
+				ForwardOutcome::ForwardOutcomeUnendorsed => Some(Bucket::GENERAL),
+				ForwardOutcome::ForwardOutcomeEndorsed => Some(Bucket::PROTECTED),
+			};
+			return Ok(InFlightHTLC {
+				timestamp_added,
+				outgoing_decision,
+				bucket,
+				expiry_height: None,
+				outgoing_index: None,
+				proposed_htlc,
+			});
+		}
+
+		return Ok(InFlightHTLC {
+			timestamp_added: SystemTime::read(reader)?,
+			outgoing_decision: ForwardOutcome::read(reader)?,
+			bucket: Option::read(reader)?,
+			expiry_height: Option::read(reader)?,
+			outgoing_index: Option::read(reader)?,
+			proposed_htlc: ProposedHTLC::read_version(reader, version)?,
+		});
+	}
+}
+
+impl Writeable for ReputationSnapshot {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.revenue.write(writer)?;
+		return self.in_flight_htlcs.write(writer);
+	}
+}
+
+impl Readable for ReputationSnapshot {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Self::read_version(reader, SNAPSHOT_VERSION);
+	}
+}
+
+impl VersionedReadable for ReputationSnapshot {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		return Ok(ReputationSnapshot {
+			revenue: DecayingAverageStart::read(reader)?,
+			in_flight_htlcs: Vec::read_version(reader, version)?,
+		});
+	}
+}
+
+impl Writeable for ChannelSnapshot {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.short_channel_id.write(writer)?;
+		self.chan_info.write(writer)?;
+		self.reputation.write(writer)?;
+		return self.target_revenue.write(writer);
+	}
+}
+
+impl Readable for ChannelSnapshot {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Self::read_version(reader, SNAPSHOT_VERSION);
+	}
+}
+
+impl VersionedReadable for ChannelSnapshot {
+	fn read_version<R: Read>(reader: &mut R, version: u8) -> Result<Self, ErrPersistence> {
+		return Ok(ChannelSnapshot {
+			short_channel_id: ShortChannelId::read(reader)?,
+			chan_info: ChannelInfo::read_version(reader, version)?,
+			reputation: Option::read_version(reader, version)?,
+			target_revenue: Option::read(reader)?,
+		});
+	}
+}
+
+impl Writeable for PeerSnapshot {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.node_id.write(writer)?;
+		return self.reputation.write(writer);
+	}
+}
+
+impl Readable for PeerSnapshot {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(PeerSnapshot {
+			node_id: NodeId::read(reader)?,
+			reputation: ReputationSnapshot::read(reader)?,
+		});
+	}
+}
+
+impl Writeable for ChannelArchive {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		self.short_channel_id.write(writer)?;
+		self.closed_at.write(writer)?;
+		self.chan_info.write(writer)?;
+		self.reputation.write(writer)?;
+		return self.revenue.write(writer);
+	}
+}
+
+impl Readable for ChannelArchive {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		return Ok(ChannelArchive {
+			short_channel_id: ShortChannelId::read(reader)?,
+			closed_at: SystemTime::read(reader)?,
+			chan_info: ChannelInfo::read(reader)?,
+			reputation: Option::read(reader)?,
+			revenue: Option::read(reader)?,
+		});
+	}
+}
+
+impl Writeable for ResourceManagerSnapshot {
+	fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+		SNAPSHOT_VERSION.write(writer)?;
+		self.manager_config.write(writer)?;
+		self.bucket_config.write(writer)?;
+		self.block_height.write(writer)?;
+		self.channels.write(writer)?;
+		self.peers.write(writer)?;
+		self.channel_aliases.write(writer)?;
+		return self.archived_channels.write(writer);
+	}
+}
+
+impl Readable for ResourceManagerSnapshot {
+	fn read<R: Read>(reader: &mut R) -> Result<Self, ErrPersistence> {
+		let version = u8::read(reader)?;
+		if !READABLE_SNAPSHOT_VERSIONS.contains(&version) {
+			return Err(ErrPersistence::UnknownVersion(version));
+		}
+
+		if version < 3 {
+			let manager_config = ManagerConfig::read_version(reader, version)?;
+			return Ok(ResourceManagerSnapshot {
+				bucket_config: BucketConfig::protected(manager_config.protected_percentage),
+				block_height: 0,
+				channels: Vec::read_version(reader, version)?,
+				peers: Vec::new(),
+				channel_aliases: Vec::new(),
+				archived_channels: Vec::new(),
+				manager_config,
+			});
+		}
+
+		return Ok(ResourceManagerSnapshot {
+			manager_config: ManagerConfig::read(reader)?,
+			bucket_config: BucketConfig::read(reader)?,
+			block_height: u32::read(reader)?,
+			channels: Vec::read(reader)?,
+			peers: Vec::read(reader)?,
+			channel_aliases: Vec::read(reader)?,
+			archived_channels: Vec::read(reader)?,
+		});
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+	use super::*;
+
+	use std::sync::Arc;
+
+	use crate::resources::clock::{Clock, MockClock};
+	use crate::resources::interface::{LocalResourceManager, ResolvedHTLC};
+	use crate::resources::reputation_tracker::outstanding_risk;
+	use crate::resources::resource_manager::ResourceManager;
+
+	#[test]
+	fn test_snapshot_round_trip() {
+		let in_flight_htlc = InFlightHTLC {
+			timestamp_added: SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+			outgoing_decision: ForwardOutcome::ForwardOutcomeEndorsed,
+			bucket: Some(Bucket::GENERAL),
+			expiry_height: Some(800_040),
+			outgoing_index: Some(3),
+			proposed_htlc: ProposedHTLC {
+				incoming_channel: ShortChannelId(1),
+				outgoing_channel: ShortChannelId(2),
+				incoming_index: 7,
+				incoming_endorsed: Endorsement::EndorsementTrue,
+				incoming_amount_msat: MilliSatoshi(11_000),
+				outgoing_amount_msat: MilliSatoshi(10_000),
+				cltv_expiry_delta: 40,
+				cltv_expiry: 800_040,
+			},
+		};
+
+		let mut encoded = Vec::new();
+		in_flight_htlc.write(&mut encoded).unwrap();
+		let decoded = InFlightHTLC::read(&mut &encoded[..]).unwrap();
+
+		assert_eq!(decoded.timestamp_added, in_flight_htlc.timestamp_added);
+		assert_eq!(decoded.outgoing_decision == ForwardOutcome::ForwardOutcomeEndorsed, true);
+		assert_eq!(decoded.bucket, Some(Bucket::GENERAL));
+		assert_eq!(decoded.expiry_height, Some(800_040));
+		assert_eq!(decoded.outgoing_index, Some(3));
+		assert_eq!(decoded.proposed_htlc.incoming_index, 7);
+		assert_eq!(decoded.proposed_htlc.cltv_expiry, 800_040);
+		assert_eq!(decoded.proposed_htlc.forwarding_fee(), Some(MilliSatoshi(1_000)));
+
+		// Truncated data and unknown versions are rejected.
+		assert_eq!(InFlightHTLC::read(&mut &encoded[..encoded.len() - 1]).err(), Some(ErrPersistence::Io(io::ErrorKind::UnexpectedEof)));
+		assert_eq!(ResourceManagerSnapshot::read(&mut &[SNAPSHOT_VERSION + 1][..]).err(), Some(ErrPersistence::UnknownVersion(SNAPSHOT_VERSION + 1)));
+		assert_eq!(ResourceManagerSnapshot::read(&mut &[0][..]).err(), Some(ErrPersistence::UnknownVersion(0)));
+
+		// Version 2 layouts varied between development builds, so they're rejected rather than misread.
+		assert_eq!(ResourceManagerSnapshot::read(&mut &[2][..]).err(), Some(ErrPersistence::UnknownVersion(2)));
+	}
+
+	/// A version 1 snapshot with a HTLC in flight from channel 1 to channel 2.
+	const SNAPSHOT_VERSION_ONE: &str = concat!(
+		"01", // Version
+		"0000000000000e1000000000180000000000000028000000000000005a00000000000000000000025800000000", // Manager config
+		"0000000000000002", // Channels
+		"0000000000000001000000000000000a00000000000186a002", // Channel 1 and its limits
+		"01000000006553f10000000000408f400000000000", // Reputation
+		"0000000000000001", // In-flight HTLCs
+		"000000006553f10000000000020000000000000001000000000000000200000007020000000000002af8000000000000271000000028", // HTLC 7, without its absolute expiry
+		"01000000006553f10000000000408f400000000000", // Target revenue
+		"0000000000000002000000000000000a00000000000186a002", // Channel 2 and its limits
+		"01000000006553f10000000000408f400000000000", // Reputation
+		"0000000000000000", // In-flight HTLCs
+		"01000000006553f10000000000408f400000000000", // Target revenue
+	);
+
+	/// A version 3 snapshot with reputation aggregated by peer, a HTLC in flight from channel 1 to channel 2,
+	/// an alias for channel 1 and an archived channel.
+	const SNAPSHOT_VERSION_THREE: &str = concat!(
+		"03", // Version
+		"0000000000000e1000000000180000000000000028000000000000005a00000000000000000000025800000000010101", // Manager config
+		"000000000000000200000000000000280000000000000028010100000000000000003c000000000000003c0000000101", // Bucket config
+		"000c3500", // Block height
+		"0000000000000002", // Channels
+		"0000000000000001000000000000000a00000000000186a002010000000000000e10000000000001020202020202020202020202020202020202020202020202020202020202020202", // Channel 1 and its limits
+		"00", // Reputation
+		"01000000006553f10000000000408f400000000000", // Target revenue
+		"0000000000000002000000000000000a00000000000186a001000000", // Channel 2 and its limits
+		"00", // Reputation
+		"01000000006553f10000000000408f400000000000", // Target revenue
+		"0000000000000001", // Peers
+		"020202020202020202020202020202020202020202020202020202020202020202", // Peer node id
+		"000000006553f10000000000408f400000000000", // Peer reputation
+		"0000000000000001", // In-flight HTLCs
+		"000000006553f1000000000002010001000c352801000000030000000000000001000000000000000200000007020000000000002af8000000000000271000000028000c3528", // HTLC 7
+		"0000000000000001", // Channel aliases
+		"00000000000000050000000000000001", // Alias 5 for channel 1
+		"0000000000000001", // Archived channels
+		"0000000000000004000000006553f10000000000000000000000000a00000000000186a00200000001407f40000000000000", // Channel 4
+	);
+
+	fn from_hex(hex: &str) -> Vec<u8> {
+		return (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+	}
+
+	#[test]
+	fn test_snapshot_version_one() {
+		let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+		let proposed_htlc = ProposedHTLC::new(ShortChannelId(1), ShortChannelId(2), 7, Endorsement::EndorsementTrue,
+			MilliSatoshi(11_000), MilliSatoshi(10_000), 40, 40);
+		let encoded = from_hex(SNAPSHOT_VERSION_ONE);
+
+		// Fields added since version 1 take their default values.
+		let snapshot = ResourceManagerSnapshot::read(&mut &encoded[..]).unwrap();
+		assert_eq!(snapshot.manager_config.protected_percentage, 40);
+		assert_eq!(snapshot.manager_config.aggregate_peer_reputation, false);
+		assert_eq!(snapshot.bucket_config.buckets, BucketConfig::protected(40).buckets);
+		assert_eq!(snapshot.channels[0].chan_info.direction, ChannelDirection::Bidirectional);
+		assert_eq!(snapshot.channels[0].chan_info.revenue_window, None);
+		assert_eq!(snapshot.peers.len(), 0);
+
+		let in_flight_htlc = &snapshot.channels[0].reputation.as_ref().unwrap().in_flight_htlcs[0];
+		assert_eq!(in_flight_htlc.bucket, Some(Bucket::PROTECTED));
+		assert_eq!(in_flight_htlc.expiry_height, None);
+		assert_eq!(in_flight_htlc.outgoing_index, None);
+
+		// The restored HTLC's expiry is set relative to the first block height that the manager is given, so
+		// that it continues to count towards the incoming channel's risk.
+		let clock = Arc::new(MockClock::new(start_time));
+		let mut resource_manager = ResourceManager::from_snapshot(snapshot, clock.clone()).unwrap();
+		assert_eq!(resource_manager.update_block_height(800_000), Ok(()));
+		let evaluated_htlc = ProposedHTLC { incoming_index: 8, ..proposed_htlc.clone() };
+		let forward_decision = resource_manager.evaluate_htlc(&evaluated_htlc).unwrap();
+		let in_flight_risk = forward_decision.reputation_check().incoming_reputation.in_flight_risk;
+		assert_eq!(in_flight_risk > 0.0, true);
+		assert_eq!(in_flight_risk, outstanding_risk(600.0, 1_000, 40, Duration::from_secs(90)));
+
+		let snapshot = resource_manager.snapshot().unwrap();
+		let channel = snapshot.channels.iter().find(|channel| channel.short_channel_id == ShortChannelId(1)).unwrap();
+		let in_flight_htlc = &channel.reputation.as_ref().unwrap().in_flight_htlcs[0];
+		assert_eq!(in_flight_htlc.expiry_height, Some(800_040));
+		assert_eq!(in_flight_htlc.proposed_htlc.cltv_expiry, 800_040);
+
+		// The restored HTLC occupies its bucket, so that it can be resolved.
+		let resolved = resource_manager.resolve_htlc(ResolvedHTLC {
+			timestamp_settled: clock.now(),
+			incoming_index: 7,
+			incoming_channel: ShortChannelId(1),
+			outgoing_index: 0,
+			outgoing_channel: ShortChannelId(2),
+			success: true,
+		});
+		assert_eq!(resolved.is_ok(), true);
+	}
+
+	#[test]
+	fn test_snapshot_version_three() {
+		let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+		let encoded = from_hex(SNAPSHOT_VERSION_THREE);
+		let snapshot = ResourceManagerSnapshot::read(&mut &encoded[..]).unwrap();
+
+		assert_eq!(snapshot.manager_config.protected_percentage, 40);
+		assert_eq!(snapshot.manager_config.aggregate_peer_reputation, true);
+		assert_eq!(snapshot.manager_config.endorsement_policy, EndorsementPolicy::EndorseIfProtected);
+		assert_eq!(snapshot.manager_config.bucketer, BucketerType::Atomic);
+		assert_eq!(snapshot.bucket_config.buckets, BucketConfig::protected(40).buckets);
+		assert_eq!(snapshot.block_height, 800_000);
+		assert_eq!(snapshot.channels[0].chan_info.revenue_window, Some(Duration::from_secs(3_600)));
+		assert_eq!(snapshot.channels[0].chan_info.peer, Some(NodeId([2; 33])));
+		assert_eq!(snapshot.channels[1].chan_info.direction, ChannelDirection::Outgoing);
+		assert_eq!(snapshot.channels[1].chan_info.in_flight_liquidity_limit, MilliSatoshi(100_000));
+		assert_eq!(snapshot.channel_aliases, vec![(ShortChannelId(5), ShortChannelId(1))]);
+		assert_eq!(snapshot.archived_channels[0].short_channel_id, ShortChannelId(4));
+		assert_eq!(snapshot.archived_channels[0].reputation, Some(500.0));
+
+		let in_flight_htlc = &snapshot.peers[0].reputation.in_flight_htlcs[0];
+		assert_eq!(in_flight_htlc.timestamp_added, start_time);
+		assert_eq!(in_flight_htlc.bucket, Some(Bucket::PROTECTED));
+		assert_eq!(in_flight_htlc.expiry_height, Some(800_040));
+		assert_eq!(in_flight_htlc.outgoing_index, Some(3));
+		assert_eq!(in_flight_htlc.proposed_htlc.cltv_expiry, 800_040);
+
+		// The current version is written with exactly the same layout, so any change to it needs a new version.
+		let mut rewritten = Vec::new();
+		snapshot.write(&mut rewritten).unwrap();
+		assert_eq!(rewritten, encoded);
+
+		let resource_manager = ResourceManager::from_snapshot(snapshot, Arc::new(MockClock::new(start_time)));
+		assert_eq!(resource_manager.is_ok(), true);
+	}
+}
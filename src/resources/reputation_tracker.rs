@@ -1,122 +1,285 @@
 
+use core::fmt;
 use core::time::Duration;
-use std::time::Instant;
+use std::time::SystemTime;
 use std::collections::HashMap;
-use std::ops::Sub;
+use std::ops::Deref;
 
+use crate::resources::clock::Clock;
 use crate::resources::decaying_average::{DecayingAverage, DecayingAverageStart};
-use crate::resources::interface::{Endorsement, ForwardOutcome, InFlightHTLC, IncomingReputation, ProposedHTLC, ReputationMonitor, ResolvedHTLC};
+use crate::resources::error::ErrResourceManager;
+use crate::resources::interface::{CircuitKey, Endorsement, ForwardDecision, InFlightHTLC, IncomingReputation, ProposedHTLC, ReputationMonitor, ResolvedHTLC};
+use crate::resources::resource_manager::ManagerConfig;
+use crate::resources::types::ShortChannelId;
 
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum ErrReputation {
 	ResolutionNotFound,
+	DuplicateHTLC,
 }
 
-pub struct ReputationTracker
+impl fmt::Display for ErrReputation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ErrReputation::ResolutionNotFound => write!(f, "resolution not found"),
+			ErrReputation::DuplicateHTLC => write!(f, "htlc already in flight"),
+		}
+	}
+}
+
+pub struct ReputationTracker<C: Deref>
+	where C::Target: Clock,
 {
 	revenue: DecayingAverage,
-	in_flight_htlcs: HashMap<u32, InFlightHTLC>,
+	/// The HTLCs in flight from the channel, keyed by the incoming side of their circuit.
+	in_flight_htlcs: HashMap<CircuitKey, InFlightHTLC>,
 	block_time: f64,
 	resolution_period: Duration,
+	clock: C,
 }
 
-impl ReputationTracker {
-	fn new() -> Self {
+/// Returns the risk that a HTLC poses to us if it is held for the number of blocks remaining until it expires.
+pub(crate) fn outstanding_risk(block_time: f64, forwarding_fee: u64, blocks_remaining: u32, resolution_period: Duration) -> f64 {
+	return (forwarding_fee as f64 * blocks_remaining as f64 * block_time * 60.0) / resolution_period.as_secs() as f64
+}
 
-		let decaying_average_start = DecayingAverageStart {
-			last_update: Instant::now(),
-			value: 0.0,
-		};
-		//TODO: reputationWindows
-		let decaying_average = DecayingAverage::new(Duration::from_secs(0), decaying_average_start);
+impl <C: Deref>ReputationTracker<C>
+	where C::Target: Clock,
+{
+	/// Creates a tracker whose reputation decays over the reputation window provided.
+	pub(crate) fn new(manager_config: ManagerConfig, reputation_window: Duration, start_value: DecayingAverageStart, clock: C) -> Self {
+
+		let decaying_average = DecayingAverage::new(reputation_window, start_value);
 
 		ReputationTracker {
 			revenue: decaying_average,
 			in_flight_htlcs: HashMap::new(),
-			block_time: 60.0 * 10.0,
-			resolution_period: Duration::from_secs(90),
+			block_time: manager_config.block_time.as_secs() as f64,
+			resolution_period: manager_config.resolution_period,
+			clock,
 		}
 	}
-}
 
-impl ReputationTracker {
-	pub(crate) fn outstanding_risk(block_time: f64, proposed_htlc: ProposedHTLC, resolution_period: Duration) -> f64 {
-		return (proposed_htlc.forwarding_fee() as f64 * proposed_htlc.cltv_expiry_delta as f64 * block_time * 60.0) /
-			resolution_period.as_secs() as f64
+	/// Returns the undecayed state of the tracker's revenue, for persistence.
+	pub(crate) fn revenue_snapshot(&self) -> DecayingAverageStart {
+		return self.revenue.snapshot();
+	}
+
+	/// Returns all of the HTLCs that are currently in flight from the channel.
+	pub(crate) fn in_flight_htlcs(&self) -> Vec<InFlightHTLC> {
+		return self.in_flight_htlcs.values().cloned().collect();
 	}
 
-	/// Returns the total outstanding risk of the incoming in-flight HTLCs from a specific channel.
-	fn in_flight_htlc_risk(&self) -> f64 {
+	/// Returns the in-flight HTLC with the incoming circuit provided, if any.
+	pub(crate) fn get_inflight(&self, incoming_circuit: &CircuitKey) -> Option<&InFlightHTLC> {
+		return self.in_flight_htlcs.get(incoming_circuit);
+	}
+
+	/// Records the index that an in-flight HTLC was assigned on its outgoing channel. An index can't be changed
+	/// once it has been assigned.
+	pub(crate) fn assign_outgoing_index(&mut self, incoming_circuit: &CircuitKey, outgoing_index: u32) -> Result<(), ErrResourceManager> {
+		let in_flight_htlc = self.in_flight_htlcs.get_mut(incoming_circuit).ok_or(ErrResourceManager::Reputation {
+			incoming_channel: incoming_circuit.short_channel_id,
+			incoming_index: incoming_circuit.htlc_index,
+			err: ErrReputation::ResolutionNotFound,
+		})?;
+
+		match in_flight_htlc.outgoing_index {
+			Some(expected) if expected != outgoing_index => return Err(ErrResourceManager::OutgoingIndexMismatch {
+				incoming_channel: incoming_circuit.short_channel_id,
+				incoming_index: incoming_circuit.htlc_index,
+				expected,
+				actual: outgoing_index,
+			}),
+			_ => in_flight_htlc.outgoing_index = Some(outgoing_index),
+		}
+
+		return Ok(());
+	}
+
+	/// Removes a HTLC that was never forwarded, without crediting or penalizing the channel's reputation.
+	pub(crate) fn cancel_inflight(&mut self, incoming_circuit: &CircuitKey) -> Option<InFlightHTLC> {
+		return self.in_flight_htlcs.remove(incoming_circuit);
+	}
+
+	/// Removes the HTLCs in flight from a channel that no longer shares the tracker, without crediting or
+	/// penalizing its reputation.
+	pub(crate) fn remove_channel_inflight(&mut self, short_channel_id: ShortChannelId) {
+		self.in_flight_htlcs.retain(|incoming_circuit, _| incoming_circuit.short_channel_id != short_channel_id);
+	}
+
+	/// Sets the expiry of HTLCs that were restored without one, taking them to expire their expiry delta after the
+	/// block height provided so that their risk is not understated.
+	pub(crate) fn set_unknown_expiries(&mut self, block_height: u32) {
+		for in_flight_htlc in self.in_flight_htlcs.values_mut().filter(|in_flight_htlc| in_flight_htlc.expiry_height.is_none()) {
+			let expiry_height = block_height.saturating_add(in_flight_htlc.proposed_htlc.cltv_expiry_delta);
+			in_flight_htlc.proposed_htlc.cltv_expiry = expiry_height;
+			in_flight_htlc.expiry_height = Some(expiry_height);
+		}
+	}
+
+	/// Restores a HTLC that was in flight when the tracker's state was persisted.
+	pub(crate) fn restore_inflight(&mut self, in_flight_htlc: InFlightHTLC) {
+		self.in_flight_htlcs.insert(in_flight_htlc.proposed_htlc.incoming_circuit(), in_flight_htlc);
+	}
+}
+
+impl <C: Deref>ReputationTracker<C>
+	where C::Target: Clock,
+{
+	/// Returns the total outstanding risk of the incoming in-flight HTLCs from a specific channel. HTLCs that
+	/// have reached their expiry height no longer pose any risk, and HTLCs whose expiry height is unknown are
+	/// treated as having their full expiry delta remaining.
+	fn in_flight_htlc_risk(&self, current_height: u32) -> f64 {
 		let mut chan_in_flight_risk = 0.0;
 
 		for (_, val) in self.in_flight_htlcs.iter() {
 			if val.proposed_htlc.incoming_endorsed != Endorsement::EndorsementTrue {
 				continue;
 			}
-			chan_in_flight_risk += Self::outstanding_risk(self.block_time, val.proposed_htlc.clone(), self.resolution_period);
+			let blocks_remaining = match val.expiry_height {
+				Some(expiry_height) => expiry_height.saturating_sub(current_height),
+				None => val.proposed_htlc.cltv_expiry_delta,
+			};
+			chan_in_flight_risk += outstanding_risk(self.block_time, val.proposed_htlc.fee_msat(), blocks_remaining,
+				self.resolution_period);
 		}
 		return chan_in_flight_risk;
 	}
 
-	fn effective_fees(&self, resolution_period: Duration, timestamp_settled: Instant, htlc: InFlightHTLC, success: bool) -> f64 {
+	/// Returns the fees that resolving the HTLC credits to the channel's reputation, less any opportunity cost
+	/// if it was held for longer than the resolution period.
+	pub(crate) fn effective_fees(&self, resolution_period: Duration, timestamp_settled: SystemTime, htlc: InFlightHTLC, success: bool) -> f64 {
 		
-		let resolution_time = timestamp_settled.sub(htlc.timestamp_added).as_secs();
+		let resolution_time = timestamp_settled.duration_since(htlc.timestamp_added).unwrap_or_default().as_secs();
 		let resolution_period_sec = resolution_period.as_secs();
-		let fee = htlc.proposed_htlc.forwarding_fee() as f64;
+		let fee = htlc.proposed_htlc.fee_msat() as f64;
 
 		//TODO: is code correct ?
-		let opportunity_cost = ((resolution_time - resolution_period_sec) / resolution_period_sec) as f64 * fee as f64;
+		let opportunity_cost = (resolution_time.saturating_sub(resolution_period_sec) / resolution_period_sec) as f64 * fee;
 
-		if htlc.proposed_htlc.incoming_endorsed == Endorsement::EndorsementTrue && success { return (fee - opportunity_cost) as f64; }
-		if htlc.proposed_htlc.incoming_endorsed == Endorsement::EndorsementTrue { return (-1 as f64 * opportunity_cost) as f64; }
-		if success { if resolution_time <= resolution_period_sec { return fee as f64; } else { return 0.0 } }
+		if htlc.proposed_htlc.incoming_endorsed == Endorsement::EndorsementTrue && success { return fee - opportunity_cost; }
+		if htlc.proposed_htlc.incoming_endorsed == Endorsement::EndorsementTrue { return -opportunity_cost; }
+		if success { if resolution_time <= resolution_period_sec { return fee; } else { return 0.0 } }
 
 		return 0.0;
 	}
 }
 
-impl ReputationMonitor for ReputationTracker {
-	fn add_inflight(&mut self, proposed_htlc: ProposedHTLC, outgoing_decision: ForwardOutcome) -> Result<bool, ErrReputation> {
+impl <C: Deref>ReputationMonitor for ReputationTracker<C>
+	where C::Target: Clock,
+{
+	fn add_inflight(&mut self, proposed_htlc: ProposedHTLC, forward_decision: &ForwardDecision) -> Result<bool, ErrResourceManager> {
+
+		let incoming_circuit = proposed_htlc.incoming_circuit();
+		if self.in_flight_htlcs.contains_key(&incoming_circuit) {
+			return Err(ErrResourceManager::Reputation {
+				incoming_channel: proposed_htlc.incoming_channel,
+				incoming_index: proposed_htlc.incoming_index,
+				err: ErrReputation::DuplicateHTLC,
+			});
+		}
 
 		let in_flight_htlc = InFlightHTLC {
-			timestamp_added: Instant::now(),
-			outgoing_decision, 
-			proposed_htlc: proposed_htlc.clone(),
+			timestamp_added: self.clock.now(),
+			outgoing_decision: forward_decision.forward_outcome.clone(),
+			bucket: forward_decision.bucket,
+			expiry_height: Some(proposed_htlc.cltv_expiry),
+			outgoing_index: None,
+			proposed_htlc,
 		};
 
-		self.in_flight_htlcs.insert(proposed_htlc.incoming_index, in_flight_htlc);
+		self.in_flight_htlcs.insert(incoming_circuit, in_flight_htlc);
 
 		return Ok(true);
 	}
 
-	fn resolve_inflight(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrReputation> {
+	fn resolve_inflight(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrResourceManager> {
 
-		if let Some(in_flight_htlc) = self.in_flight_htlcs.remove(&resolved_htlc.incoming_index) {
+		let incoming_circuit = resolved_htlc.incoming_circuit();
+		if let Some(in_flight_htlc) = self.in_flight_htlcs.get(&incoming_circuit) {
 			let effective_fees = self.effective_fees(self.resolution_period, resolved_htlc.timestamp_settled, in_flight_htlc.clone(), resolved_htlc.success);
 			
-			self.revenue.add(effective_fees);
-				
-			return Ok(in_flight_htlc);
+			self.revenue.add(effective_fees, self.clock.now()).map_err(|err| ErrResourceManager::DecayingAverage {
+				short_channel_id: resolved_htlc.incoming_channel,
+				err,
+			})?;
+
+			return Ok(self.in_flight_htlcs.remove(&incoming_circuit).unwrap());
 		}
-		return Err(ErrReputation::ResolutionNotFound);
+		return Err(ErrResourceManager::Reputation {
+			incoming_channel: resolved_htlc.incoming_channel,
+			incoming_index: resolved_htlc.incoming_index,
+			err: ErrReputation::ResolutionNotFound,
+		});
 	}
 
-	fn incoming_reputation(&mut self) -> IncomingReputation {
+	fn incoming_reputation(&mut self, current_height: u32) -> IncomingReputation {
 		return IncomingReputation {
-			incoming_revenue: self.revenue.get_value(),
-			in_flight_risk: self.in_flight_htlc_risk(),
+			incoming_revenue: self.revenue.get_value(self.clock.now()),
+			in_flight_risk: self.in_flight_htlc_risk(current_height),
 		}
 	}
 }
 
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 	use super::*;
 
+	use crate::resources::clock::SystemClock;
+	use crate::resources::interface::{Bucket, ForwardOutcome, ReputationCheck};
+	use crate::resources::types::MilliSatoshi;
+
 	#[test]
 	fn test_reputation_tracker() {
-		let reputation_tracker = ReputationTracker::new();
+		let decaying_average_start = DecayingAverageStart {
+			last_update: SystemClock.now(),
+			value: 0.0,
+		};
+		let manager_config = ManagerConfig::default();
+		let reputation_tracker = ReputationTracker::new(manager_config, manager_config.reputation_window(), decaying_average_start, &SystemClock);
+		assert_eq!(reputation_tracker.in_flight_htlcs.len(), 0);
+	}
+
+	#[test]
+	fn test_reputation_tracker_in_flight_risk() {
+		let decaying_average_start = DecayingAverageStart {
+			last_update: SystemClock.now(),
+			value: 0.0,
+		};
+		let manager_config = ManagerConfig::default();
+		let mut reputation_tracker = ReputationTracker::new(manager_config, manager_config.reputation_window(), decaying_average_start, &SystemClock);
+
+		let proposed_htlc = ProposedHTLC::new(ShortChannelId(1), ShortChannelId(2), 0, Endorsement::EndorsementTrue, MilliSatoshi(11_000), MilliSatoshi(10_000), 40, 800_040);
+		let forward_decision = ForwardDecision {
+			reputation_check: ReputationCheck {
+				incoming_reputation: IncomingReputation { incoming_revenue: 0.0, in_flight_risk: 0.0 },
+				outgoing_revenue: 0.0,
+				htlc_risk: 0.0,
+			},
+			forward_outcome: ForwardOutcome::ForwardOutcomeEndorsed,
+			bucket: Some(Bucket::PROTECTED),
+			outgoing_endorsement: Endorsement::EndorsementTrue,
+		};
+		let ret = reputation_tracker.add_inflight(proposed_htlc.clone(), &forward_decision);
+		assert_eq!(ret.is_ok(), true);
+		assert_eq!(reputation_tracker.get_inflight(&proposed_htlc.incoming_circuit()).unwrap().expiry_height, Some(800_040));
+
+		// The same HTLC can't be added twice.
+		assert_eq!(reputation_tracker.add_inflight(proposed_htlc, &forward_decision).err(), Some(ErrResourceManager::Reputation {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 0,
+			err: ErrReputation::DuplicateHTLC,
+		}));
+
+		// Risk is evaluated for the blocks remaining until expiry, rather than the HTLC's original expiry delta.
+		let full_risk = outstanding_risk(600.0, 1_000, 40, manager_config.resolution_period);
+		assert_eq!(full_risk, 16_000_000.0);
+		assert_eq!(reputation_tracker.incoming_reputation(800_000).in_flight_risk, full_risk);
+		assert_eq!(reputation_tracker.incoming_reputation(800_030).in_flight_risk, full_risk / 4.0);
+		assert_eq!(reputation_tracker.incoming_reputation(800_050).in_flight_risk, 0.0);
 	}
 }
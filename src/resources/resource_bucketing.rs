@@ -1,8 +1,11 @@
 
 
+use core::fmt;
+use std::collections::HashMap;
 use std::sync::Mutex;
 
-use crate::resources::interface::ResourceBucketer;
+use crate::resources::interface::{Bucket, BucketUsage, HTLCClass, ResourceBucketer};
+use crate::resources::types::{MilliSatoshi, ShortChannelId};
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum ErrBucketing {
@@ -10,46 +13,345 @@ pub enum ErrBucketing {
 	ProtectedPercentage,
 	NoInFlightLiquidity,
 	NoHTLCSlotsOccupied,
+	LockPoisoned,
+	InvalidBucketConfig,
+	UnknownBucket,
+	FairShareUnsupported,
 }
 
-/// A resource manager that reserves a percentage of resources
-/// for HTLCs that are protected.
+impl fmt::Display for ErrBucketing {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ErrBucketing::ProtocolLimits => write!(f, "slots above protocol limits"),
+			ErrBucketing::ProtectedPercentage => write!(f, "invalid protected percentage"),
+			ErrBucketing::NoInFlightLiquidity => write!(f, "no in flight liquidity"),
+			ErrBucketing::NoHTLCSlotsOccupied => write!(f, "no htlc slots occupied"),
+			ErrBucketing::LockPoisoned => write!(f, "bucket lock poisoned"),
+			ErrBucketing::InvalidBucketConfig => write!(f, "invalid bucket configuration"),
+			ErrBucketing::UnknownBucket => write!(f, "unknown bucket"),
+			ErrBucketing::FairShareUnsupported => write!(f, "fair share not supported by bucketer"),
+		}
+	}
+}
+
+/// This describes a single bucket of an outgoing channel's resources.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BucketTier {
+	/// Percentage of the channel's HTLC slots assigned to the bucket.
+	pub slot_percentage: u64,
+	/// Percentage of the channel's liquidity assigned to the bucket.
+	pub liquidity_percentage: u64,
+	/// The bucket that HTLCs may use once this bucket is full, if any. A bucket may only overflow into a bucket
+	/// that comes after it.
+	pub overflow: Option<Bucket>,
+	/// The percentage of the bucket's slots and liquidity that a single incoming channel may occupy, if limited.
+	/// This prevents one incoming channel from filling the bucket and starving all others.
+	pub fair_share_percentage: Option<u64>,
+}
+
+/// This describes how an outgoing channel's resources are split into buckets, and the bucket that each class of
+/// HTLC is assigned to.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BucketConfig {
+	/// The buckets that resources are split into, indexed by [`Bucket`]. The slot and liquidity percentages of
+	/// all buckets must each add up to 100.
+	pub buckets: Vec<BucketTier>,
+	/// The bucket for endorsed HTLCs from incoming channels with sufficient reputation.
+	pub endorsed_sufficient_reputation: Bucket,
+	/// The bucket for endorsed HTLCs from incoming channels without sufficient reputation.
+	pub endorsed_insufficient_reputation: Bucket,
+	/// The bucket for unendorsed HTLCs.
+	pub unendorsed: Bucket,
+}
+
+impl BucketConfig {
+	/// Creates a configuration that reserves a percentage of resources for endorsed HTLCs from channels with
+	/// sufficient reputation, which overflow into the general resources used by all other HTLCs once full.
+	///
+	/// Incoming channels are not limited to a fair share of either bucket, see [`BucketConfig::with_general_fair_share`].
+	pub fn protected(protected_percentage: u64) -> Self {
+		let general_percentage = 100_u64.saturating_sub(protected_percentage);
+
+		return BucketConfig {
+			buckets: vec![
+				BucketTier {
+					slot_percentage: protected_percentage,
+					liquidity_percentage: protected_percentage,
+					overflow: Some(Bucket::GENERAL),
+					fair_share_percentage: None,
+				},
+				BucketTier {
+					slot_percentage: general_percentage,
+					liquidity_percentage: general_percentage,
+					overflow: None,
+					fair_share_percentage: None,
+				},
+			],
+			endorsed_sufficient_reputation: Bucket::PROTECTED,
+			endorsed_insufficient_reputation: Bucket::GENERAL,
+			unendorsed: Bucket::GENERAL,
+		};
+	}
+
+	/// Limits each incoming channel to a percentage of the general bucket's slots and liquidity.
+	pub fn with_general_fair_share(mut self, fair_share_percentage: u64) -> Self {
+		if let Some(tier) = self.buckets.get_mut(Bucket::GENERAL.0 as usize) {
+			tier.fair_share_percentage = Some(fair_share_percentage);
+		}
+
+		return self;
+	}
+
+	pub(crate) fn validate(&self) -> Result<(), ErrBucketing> {
+		if self.buckets.is_empty() || self.buckets.len() > u8::MAX as usize {
+			return Err(ErrBucketing::InvalidBucketConfig);
+		}
+
+		if self.buckets.iter().map(|tier| tier.slot_percentage).sum::<u64>() != 100 ||
+			self.buckets.iter().map(|tier| tier.liquidity_percentage).sum::<u64>() != 100 {
+			return Err(ErrBucketing::InvalidBucketConfig);
+		}
+
+		// Only allowing overflow into later buckets ensures that overflow can't loop.
+		for (index, tier) in self.buckets.iter().enumerate() {
+			if tier.fair_share_percentage.is_some_and(|percentage| percentage == 0 || percentage > 100) {
+				return Err(ErrBucketing::InvalidBucketConfig);
+			}
+
+			if let Some(overflow) = tier.overflow {
+				if (overflow.0 as usize) <= index || !self.has_bucket(overflow) {
+					return Err(ErrBucketing::InvalidBucketConfig);
+				}
+			}
+		}
+
+		if !self.has_bucket(self.endorsed_sufficient_reputation) || !self.has_bucket(self.endorsed_insufficient_reputation) ||
+			!self.has_bucket(self.unendorsed) {
+			return Err(ErrBucketing::InvalidBucketConfig);
+		}
+
+		return Ok(());
+	}
+
+	fn has_bucket(&self, bucket: Bucket) -> bool {
+		return (bucket.0 as usize) < self.buckets.len();
+	}
+
+	/// Splits a channel's liquidity and slots between buckets, returning the limits of each bucket.
+	pub(crate) fn split(&self, total_liquidity_msat: u64, total_slots: u64) -> Result<Vec<(u64, u64)>, ErrBucketing> {
+		if total_slots > 483 {
+			return Err(ErrBucketing::ProtocolLimits);
+		}
+
+		let liquidity = split_by_percentage(total_liquidity_msat, self.buckets.iter().map(|tier| tier.liquidity_percentage).collect());
+		let slots = split_by_percentage(total_slots, self.buckets.iter().map(|tier| tier.slot_percentage).collect());
+
+		return Ok(liquidity.into_iter().zip(slots).collect());
+	}
+
+	pub(crate) fn assigned_bucket(&self, htlc_class: HTLCClass) -> Bucket {
+		return match htlc_class {
+			HTLCClass::EndorsedSufficientReputation => self.endorsed_sufficient_reputation,
+			HTLCClass::EndorsedInsufficientReputation => self.endorsed_insufficient_reputation,
+			HTLCClass::Unendorsed => self.unendorsed,
+		};
+	}
+}
+
+/// A resource manager that splits a channel's resources into buckets, reserving resources for different
+/// classes of HTLC.
+///
+/// HTLCs occupy the bucket that their class is assigned to while it has space, and then any bucket that it
+/// overflows into. By default, a percentage of resources is reserved for protected HTLCs.
 pub struct BucketResourceManager
 {
+	config: BucketConfig,
 	mut_bucket_resource_manager: Mutex<MutBucketResourceManager>
 }
 
-struct MutBucketResourceManager {
-	/// General liquidity available.
-	general_liquidity_msat: u64,
-	/// General slots available.
-	general_slots: u64,
+/// Tracks the slots and liquidity that are in use.
+#[derive(Default)]
+struct Usage {
 	/// In flight liquidity locked up.
 	in_flight_liquidity_msat: u64,
 	/// In flight HTLCs slots locked up.
 	in_flight_slots: u64,
 }
 
-impl BucketResourceManager {
-	pub(crate) fn new(total_liquidity_msat: u64, total_slots: u64, protected_percentage: u64) -> Result<Self, ErrBucketing> {
-		if total_slots > 483 {
-			return Err(ErrBucketing::ProtocolLimits);
+/// Tracks the resources available to a single bucket and how many of them are in use.
+struct BucketState {
+	/// Liquidity available.
+	liquidity_msat: u64,
+	/// Slots available.
+	slots: u64,
+	/// In flight liquidity locked up.
+	in_flight_liquidity_msat: u64,
+	/// In flight HTLCs slots locked up.
+	in_flight_slots: u64,
+	/// The liquidity and slots that a single incoming channel may occupy, if limited.
+	fair_share: Option<(u64, u64)>,
+	/// The resources in use by each incoming channel, tracked when the bucket limits incoming channels to a fair share.
+	incoming_usage: HashMap<ShortChannelId, Usage>,
+}
+
+impl BucketState {
+	fn new(liquidity_msat: u64, slots: u64, fair_share_percentage: Option<u64>) -> Self {
+		let mut state = BucketState {
+			liquidity_msat: 0,
+			slots: 0,
+			in_flight_liquidity_msat: 0,
+			in_flight_slots: 0,
+			fair_share: None,
+			incoming_usage: HashMap::new(),
+		};
+		state.set_limits(liquidity_msat, slots, fair_share_percentage);
+
+		return state;
+	}
+
+	/// Sets the resources available to the bucket, leaving the resources that are in use unchanged.
+	fn set_limits(&mut self, liquidity_msat: u64, slots: u64, fair_share_percentage: Option<u64>) {
+		self.liquidity_msat = liquidity_msat;
+		self.slots = slots;
+
+		// Every incoming channel is allowed at least one slot, so that small buckets remain usable.
+		self.fair_share = fair_share_percentage.map(|percentage| (
+			percentage_of(liquidity_msat, percentage),
+			percentage_of(slots, percentage).max(1),
+		));
+	}
+
+	fn has_space(&self, incoming_channel: ShortChannelId, htlc_amount_msat: MilliSatoshi) -> bool {
+		// Amounts that overflow can't fit in the bucket.
+		if self.in_flight_liquidity_msat.checked_add(htlc_amount_msat.to_msat()).is_none_or(|total| total > self.liquidity_msat) ||
+			self.in_flight_slots >= self.slots {
+			return false;
+		}
+
+		if let Some((liquidity_share_msat, slot_share)) = self.fair_share {
+			if let Some(usage) = self.incoming_usage.get(&incoming_channel) {
+				return usage.in_flight_liquidity_msat.checked_add(htlc_amount_msat.to_msat()).is_some_and(|total| total <= liquidity_share_msat) &&
+					usage.in_flight_slots < slot_share;
+			}
+			return htlc_amount_msat.to_msat() <= liquidity_share_msat;
+		}
+
+		return true;
+	}
+
+	fn add(&mut self, incoming_channel: ShortChannelId, htlc_amount_msat: MilliSatoshi) -> bool {
+		if !self.has_space(incoming_channel, htlc_amount_msat) {
+			return false;
 		}
 
+		return self.restore(incoming_channel, htlc_amount_msat);
+	}
+
+	/// Occupies resources for a HTLC without checking the bucket's limits, so that HTLCs which were already in
+	/// flight can be tracked even if the bucket has since shrunk. Returns false if the usage would overflow.
+	fn restore(&mut self, incoming_channel: ShortChannelId, htlc_amount_msat: MilliSatoshi) -> bool {
+		self.in_flight_liquidity_msat = match self.in_flight_liquidity_msat.checked_add(htlc_amount_msat.to_msat()) {
+			Some(in_flight_liquidity_msat) => in_flight_liquidity_msat,
+			None => return false,
+		};
+		self.in_flight_slots += 1;
+
+		if self.fair_share.is_some() {
+			let usage = self.incoming_usage.entry(incoming_channel).or_default();
+			usage.in_flight_liquidity_msat += htlc_amount_msat.to_msat();
+			usage.in_flight_slots += 1;
+		}
+
+		return true;
+	}
+
+	fn remove(&mut self, incoming_channel: ShortChannelId, htlc_amount_msat: MilliSatoshi) -> Result<bool, ErrBucketing> {
+		if self.in_flight_liquidity_msat < htlc_amount_msat.to_msat() {
+			return Err(ErrBucketing::NoInFlightLiquidity);
+		}
+
+		if self.in_flight_slots == 0 {
+			return Err(ErrBucketing::NoHTLCSlotsOccupied);
+		}
+
+		self.in_flight_liquidity_msat -= htlc_amount_msat.to_msat();
+		self.in_flight_slots -= 1;
+
+		if let Some(usage) = self.incoming_usage.get_mut(&incoming_channel) {
+			usage.in_flight_liquidity_msat = usage.in_flight_liquidity_msat.saturating_sub(htlc_amount_msat.to_msat());
+			usage.in_flight_slots = usage.in_flight_slots.saturating_sub(1);
+
+			if usage.in_flight_slots == 0 {
+				self.incoming_usage.remove(&incoming_channel);
+			}
+		}
+
+		return Ok(true);
+	}
+
+	fn usage(&self) -> BucketUsage {
+		return BucketUsage {
+			in_flight_slots: self.in_flight_slots,
+			slot_limit: self.slots,
+			in_flight_liquidity_msat: MilliSatoshi(self.in_flight_liquidity_msat),
+			liquidity_limit_msat: MilliSatoshi(self.liquidity_msat),
+		};
+	}
+}
+
+struct MutBucketResourceManager {
+	/// The state of each bucket, indexed by bucket.
+	buckets: Vec<BucketState>,
+}
+
+impl MutBucketResourceManager {
+	fn bucket(&mut self, bucket: Bucket) -> Option<&mut BucketState> {
+		return self.buckets.get_mut(bucket.0 as usize);
+	}
+}
+
+/// Returns a percentage of a value, calculated without overflow.
+fn percentage_of(value: u64, percentage: u64) -> u64 {
+	return (value as u128 * percentage as u128 / 100) as u64;
+}
+
+/// Splits a total between buckets by percentage, with any remainder from rounding given to the last bucket.
+fn split_by_percentage(total: u64, percentages: Vec<u64>) -> Vec<u64> {
+	let mut shares: Vec<u64> = percentages.iter().map(|percentage| percentage_of(total, *percentage)).collect();
+
+	let allocated: u64 = shares.iter().sum();
+	if let Some(last) = shares.last_mut() {
+		*last += total - allocated;
+	}
+
+	return shares;
+}
+
+impl BucketResourceManager {
+	/// Creates a bucketer that reserves a percentage of resources for protected HTLCs.
+	pub fn new(total_liquidity_msat: MilliSatoshi, total_slots: u64, protected_percentage: u64) -> Result<Self, ErrBucketing> {
 		if protected_percentage > 100 {
 			return Err(ErrBucketing::ProtectedPercentage);
 		}
 
-		let protected_liquidity = total_liquidity_msat * protected_percentage / 100;
-		let protected_slots = total_slots * protected_percentage / 100;
+		return BucketResourceManager::with_config(total_liquidity_msat, total_slots, BucketConfig::protected(protected_percentage));
+	}
+
+	/// Creates a bucketer that splits resources according to the configuration provided.
+	pub fn with_config(total_liquidity_msat: MilliSatoshi, total_slots: u64, config: BucketConfig) -> Result<Self, ErrBucketing> {
+		config.validate()?;
+
+		let buckets = config.split(total_liquidity_msat.to_msat(), total_slots)?.into_iter().zip(config.buckets.iter())
+			.map(|((liquidity_msat, slots), tier)| BucketState::new(liquidity_msat, slots, tier.fair_share_percentage))
+			.collect();
 
 		Ok(BucketResourceManager {
+			config,
 			mut_bucket_resource_manager: Mutex::new(
 				MutBucketResourceManager {
-					general_liquidity_msat: total_liquidity_msat - protected_liquidity,
-					general_slots: total_slots - protected_slots,
-					in_flight_liquidity_msat: 0,
-					in_flight_slots: 0,
+					buckets,
 				}
 			)
 		})
@@ -57,68 +359,212 @@ impl BucketResourceManager {
 }
 
 impl ResourceBucketer for BucketResourceManager {
-	fn add_htlc(&self, protected: bool, htlc_amount_msat: u64) -> bool {
-		if protected {
-			return true;
-		}
+	fn add_htlc(&self, incoming_channel: ShortChannelId, htlc_class: HTLCClass, htlc_amount_msat: MilliSatoshi) -> Option<Bucket> {
+		let mut mut_brm = match self.mut_bucket_resource_manager.lock() {
+			Ok(mut_brm) => mut_brm,
+			Err(_) => return None,
+		};
 
-		if let Ok(ref mut mut_brm) = self.mut_bucket_resource_manager.lock() {
-			if mut_brm.in_flight_liquidity_msat + htlc_amount_msat > mut_brm.general_liquidity_msat {
-				return false;
+		let mut bucket = self.config.assigned_bucket(htlc_class);
+		loop {
+			if mut_brm.bucket(bucket)?.add(incoming_channel, htlc_amount_msat) {
+				return Some(bucket);
 			}
 
-			if mut_brm.in_flight_slots+1 > mut_brm.general_slots {
-				return false;
+			bucket = self.config.buckets[bucket.0 as usize].overflow?;
+		}
+	}
+
+	fn available_bucket(&self, incoming_channel: ShortChannelId, htlc_class: HTLCClass, htlc_amount_msat: MilliSatoshi) -> Option<Bucket> {
+		let mut_brm = self.mut_bucket_resource_manager.lock().ok()?;
+
+		let mut bucket = self.config.assigned_bucket(htlc_class);
+		loop {
+			if mut_brm.buckets.get(bucket.0 as usize)?.has_space(incoming_channel, htlc_amount_msat) {
+				return Some(bucket);
 			}
 
-			mut_brm.in_flight_liquidity_msat += htlc_amount_msat;
-			mut_brm.in_flight_slots += 1;
+			bucket = self.config.buckets[bucket.0 as usize].overflow?;
 		}
+	}
 
-		return true;
+	fn restore_htlc(&self, incoming_channel: ShortChannelId, bucket: Bucket, htlc_amount_msat: MilliSatoshi) -> bool {
+		return match self.mut_bucket_resource_manager.lock() {
+			Ok(mut mut_brm) => mut_brm.bucket(bucket).is_some_and(|state| state.restore(incoming_channel, htlc_amount_msat)),
+			Err(_) => false,
+		};
 	}
 
-	fn remove_htlc(&self, protected: bool, htlc_amount_msat: u64) -> Result<bool, ErrBucketing> 
+	fn remove_htlc(&self, incoming_channel: ShortChannelId, bucket: Bucket, htlc_amount_msat: MilliSatoshi) -> Result<bool, ErrBucketing>
 	{
-		if protected {
-			return Ok(true);
-		}
+		let mut mut_brm = self.mut_bucket_resource_manager.lock().map_err(|_| ErrBucketing::LockPoisoned)?;
+		return mut_brm.bucket(bucket).ok_or(ErrBucketing::UnknownBucket)?.remove(incoming_channel, htlc_amount_msat);
+	}
 
-		if let Ok(ref mut mut_brm) = self.mut_bucket_resource_manager.lock() {
-			if mut_brm.in_flight_liquidity_msat < htlc_amount_msat {
-				return Err(ErrBucketing::NoInFlightLiquidity);
-			}
+	fn occupancy(&self) -> Vec<BucketUsage> {
+		// A poisoned lock is reported as having no resources, consistent with the bucketer refusing new HTLCs.
+		return match self.mut_bucket_resource_manager.lock() {
+			Ok(mut_brm) => mut_brm.buckets.iter().map(|state| state.usage()).collect(),
+			Err(_) => self.config.buckets.iter().map(|_| BucketState::new(0, 0, None).usage()).collect(),
+		};
+	}
 
-			if mut_brm.in_flight_slots == 0 {
-				return Err(ErrBucketing::NoHTLCSlotsOccupied);
-			}
+	fn resize(&self, total_liquidity_msat: MilliSatoshi, total_slots: u64) -> Result<(), ErrBucketing> {
+		let limits = self.config.split(total_liquidity_msat.to_msat(), total_slots)?;
 
-			mut_brm.in_flight_liquidity_msat -= htlc_amount_msat;
-			mut_brm.in_flight_slots -= 1;
+		let mut mut_brm = self.mut_bucket_resource_manager.lock().map_err(|_| ErrBucketing::LockPoisoned)?;
+		for ((state, (liquidity_msat, slots)), tier) in mut_brm.buckets.iter_mut().zip(limits).zip(self.config.buckets.iter()) {
+			state.set_limits(liquidity_msat, slots, tier.fair_share_percentage);
 		}
 
-		return Ok(true);
+		return Ok(());
 	}
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 	use super::*;
 
 	#[test]
 	fn test_bucket_resource_manager() {
-		let bucket_resource_manager_one = BucketResourceManager::new(100_000, 300, 50);
+		let bucket_resource_manager_one = BucketResourceManager::new(MilliSatoshi(100_000), 300, 50);
 		assert_eq!(bucket_resource_manager_one.is_ok(), true);
-		let bucket_resource_manager_two = BucketResourceManager::new(100_000, 500, 50);
+		let bucket_resource_manager_two = BucketResourceManager::new(MilliSatoshi(100_000), 500, 50);
 		assert_eq!(bucket_resource_manager_two.is_err(), true);
 	}
 
 	#[test]
 	fn test_bucket_resource_manager_update_htlc() {
-		let mut bucket_resource_manager = BucketResourceManager::new(100_000, 300, 50).unwrap();
+		let bucket_resource_manager = BucketResourceManager::new(MilliSatoshi(100_000), 300, 50).unwrap();
 
-		bucket_resource_manager.add_htlc(false, 5_000);
-		let ret = bucket_resource_manager.remove_htlc(false, 5_000);
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), HTLCClass::Unendorsed, MilliSatoshi(5_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucket_resource_manager.occupancy()[1], BucketUsage {
+			in_flight_slots: 1,
+			slot_limit: 150,
+			in_flight_liquidity_msat: MilliSatoshi(5_000),
+			liquidity_limit_msat: MilliSatoshi(50_000),
+		});
+		let ret = bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(5_000));
 		assert_eq!(ret.is_ok(), true);
+		assert_eq!(bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(5_000)), Err(ErrBucketing::NoInFlightLiquidity));
+		assert_eq!(bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket(2), MilliSatoshi(5_000)), Err(ErrBucketing::UnknownBucket));
+	}
+
+	#[test]
+	fn test_bucket_resource_manager_protected_overflow() {
+		let bucket_resource_manager = BucketResourceManager::new(MilliSatoshi(100_000), 4, 50).unwrap();
+		let protected = HTLCClass::EndorsedSufficientReputation;
+
+		// Protected HTLCs fill the protected bucket, then overflow into the general bucket.
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), protected, MilliSatoshi(40_000)), Some(Bucket::PROTECTED));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), protected, MilliSatoshi(20_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), protected, MilliSatoshi(5_000)), Some(Bucket::PROTECTED));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), protected, MilliSatoshi(5_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), protected, MilliSatoshi(1)), None);
+
+		let occupancy = bucket_resource_manager.occupancy();
+		assert_eq!(occupancy[0].in_flight_slots, 2);
+		assert_eq!(occupancy[0].in_flight_liquidity_msat, MilliSatoshi(45_000));
+		assert_eq!(occupancy[1].in_flight_slots, 2);
+		assert_eq!(occupancy[1].in_flight_liquidity_msat, MilliSatoshi(25_000));
+
+		// Other HTLCs can't use the protected bucket, even when it has space.
+		assert_eq!(bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket::PROTECTED, MilliSatoshi(5_000)), Ok(true));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), HTLCClass::EndorsedInsufficientReputation, MilliSatoshi(1_000)), None);
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), protected, MilliSatoshi(1_000)), Some(Bucket::PROTECTED));
+	}
+
+	#[test]
+	fn test_bucket_resource_manager_tiers() {
+		let tier = |percentage: u64, overflow: Option<Bucket>| BucketTier {
+			slot_percentage: percentage,
+			liquidity_percentage: percentage,
+			overflow,
+			fair_share_percentage: None,
+		};
+
+		// Endorsed HTLCs without sufficient reputation get their own bucket, which overflows into the unendorsed bucket.
+		let config = BucketConfig {
+			buckets: vec![tier(40, Some(Bucket(1))), tier(30, Some(Bucket(2))), tier(30, None)],
+			endorsed_sufficient_reputation: Bucket(0),
+			endorsed_insufficient_reputation: Bucket(1),
+			unendorsed: Bucket(2),
+		};
+		let bucket_resource_manager = BucketResourceManager::with_config(MilliSatoshi(1_000), 10, config.clone()).unwrap();
+
+		let occupancy = bucket_resource_manager.occupancy();
+		assert_eq!(occupancy.iter().map(|usage| usage.slot_limit).collect::<Vec<u64>>(), vec![4, 3, 3]);
+		assert_eq!(occupancy.iter().map(|usage| usage.liquidity_limit_msat).collect::<Vec<MilliSatoshi>>(), vec![MilliSatoshi(400), MilliSatoshi(300), MilliSatoshi(300)]);
+
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), HTLCClass::EndorsedInsufficientReputation, MilliSatoshi(250)), Some(Bucket(1)));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), HTLCClass::EndorsedInsufficientReputation, MilliSatoshi(250)), Some(Bucket(2)));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), HTLCClass::Unendorsed, MilliSatoshi(100)), None);
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), HTLCClass::EndorsedSufficientReputation, MilliSatoshi(400)), Some(Bucket(0)));
+
+		// Percentages must cover all resources, and buckets may only overflow into later buckets.
+		let invalid_share = BucketConfig { buckets: vec![tier(40, None), tier(30, None)], ..config.clone() };
+		assert_eq!(invalid_share.validate(), Err(ErrBucketing::InvalidBucketConfig));
+		let invalid_overflow = BucketConfig { buckets: vec![tier(40, None), tier(30, Some(Bucket(0))), tier(30, None)], ..config };
+		assert_eq!(invalid_overflow.validate(), Err(ErrBucketing::InvalidBucketConfig));
+	}
+
+	#[test]
+	fn test_bucket_resource_manager_fair_share() {
+		let config = BucketConfig::protected(50).with_general_fair_share(50);
+		let bucket_resource_manager = BucketResourceManager::with_config(MilliSatoshi(100_000), 8, config).unwrap();
+		let unendorsed = HTLCClass::Unendorsed;
+
+		// Channel 1 may only use half of the general bucket's 4 slots and 50_000 msat.
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(20_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(10_000)), None);
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(5_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(1)), None);
+
+		// Other channels still have their share of the bucket.
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(2), unendorsed, MilliSatoshi(25_000)), Some(Bucket::GENERAL));
+
+		// Resolving a HTLC frees up the channel's share.
+		assert_eq!(bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(5_000)), Ok(true));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(5_000)), Some(Bucket::GENERAL));
+
+		// Amounts that overflow the bucket's or the channel's usage are refused rather than wrapping.
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(u64::MAX)), None);
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(3), unendorsed, MilliSatoshi(u64::MAX)), None);
+		let unlimited = BucketResourceManager::with_config(MilliSatoshi(u64::MAX), 8, BucketConfig::protected(0).with_general_fair_share(100)).unwrap();
+		assert_eq!(unlimited.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(1)), Some(Bucket::GENERAL));
+		assert_eq!(unlimited.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(u64::MAX)), None);
+
+		let invalid_config = BucketConfig::protected(50).with_general_fair_share(0);
+		assert_eq!(invalid_config.validate(), Err(ErrBucketing::InvalidBucketConfig));
+	}
+
+	#[test]
+	fn test_bucket_resource_manager_resize() {
+		let bucket_resource_manager = BucketResourceManager::new(MilliSatoshi(100_000), 8, 50).unwrap();
+		let unendorsed = HTLCClass::Unendorsed;
+
+		for _ in 0..3 {
+			assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(10_000)), Some(Bucket::GENERAL));
+		}
+
+		// Shrinking the channel keeps HTLCs in flight, but refuses new ones until usage is under the new limits.
+		assert_eq!(bucket_resource_manager.resize(MilliSatoshi(40_000), 4), Ok(()));
+		assert_eq!(bucket_resource_manager.occupancy()[1], BucketUsage {
+			in_flight_slots: 3,
+			slot_limit: 2,
+			in_flight_liquidity_msat: MilliSatoshi(30_000),
+			liquidity_limit_msat: MilliSatoshi(20_000),
+		});
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(1)), None);
+
+		assert_eq!(bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(10_000)), Ok(true));
+		assert_eq!(bucket_resource_manager.remove_htlc(ShortChannelId(1), Bucket::GENERAL, MilliSatoshi(10_000)), Ok(true));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(10_000)), Some(Bucket::GENERAL));
+
+		// Growing the channel makes new resources available immediately.
+		assert_eq!(bucket_resource_manager.resize(MilliSatoshi(200_000), 8), Ok(()));
+		assert_eq!(bucket_resource_manager.add_htlc(ShortChannelId(1), unendorsed, MilliSatoshi(60_000)), Some(Bucket::GENERAL));
+		assert_eq!(bucket_resource_manager.resize(MilliSatoshi(200_000), 500), Err(ErrBucketing::ProtocolLimits));
 	}
 }
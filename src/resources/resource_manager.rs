@@ -1,31 +1,91 @@
-
 use core::time::Duration;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
-use std::time::Instant;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
 
-use crate::resources::decaying_average::{DecayingAverage, DecayingAverageStart};
-use crate::resources::reputation_tracker::ReputationTracker;
+use crate::resources::atomic_resource_bucketing::AtomicBucketResourceManager;
+use crate::resources::audit::{AuditEvent, AuditSink, CancelAudit, ForwardAudit, ResolveAudit};
+use crate::resources::clock::Clock;
+use crate::resources::decaying_average::DecayingAverageStart;
+use crate::resources::error::ErrResourceManager;
+use crate::resources::persistence::{ChannelSnapshot, PeerSnapshot, ReputationSnapshot, ResourceManagerSnapshot};
+use crate::resources::reputation_tracker::{ErrReputation, ReputationTracker};
 use crate::resources::target_tracker::TargetChannelTracker;
-use crate::resources::interface::{ChannelInfo, ForwardDecision, ForwardOutcome, InFlightHTLC, LocalResourceManager, ProposedHTLC, ReputationCheck, ReputationMonitor, ResourceBucketer, ResolvedHTLC, TargetMonitor};
-use crate::resources::resource_bucketing::BucketResourceManager;
+use crate::resources::interface::{ChannelInfo, CircuitKey, Endorsement, ForwardDecision, ForwardOutcome, HTLCClass, InFlightHTLC, LocalResourceManager, NodeId, ProposedHTLC, ReputationMonitor, ResourceBucketer, ResolvedHTLC, TargetMonitor};
+use crate::resources::resource_bucketing::{BucketConfig, BucketResourceManager};
+use crate::resources::types::{MilliSatoshi, ShortChannelId};
+
+const MAX_MILLI_SATOSHI: MilliSatoshi = MilliSatoshi(21_000_000 * 1000);
 
-const MAX_MILLI_SATOSHI: u64 = 21_000_000 * 1000;
+/// The resource bucketer used for each outgoing channel. It must be shareable across threads so that
+/// forwards from different incoming channels can reserve its resources concurrently.
+type ChannelBucketer = Box<dyn ResourceBucketer + Send + Sync>;
+
+/// This determines the endorsement signal that is set on the outgoing HTLC for a forward.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EndorsementPolicy {
+	/// Forwarded HTLCs carry the endorsement signal that they were received with, whether or not they were
+	/// protected.
+	RelayAsReceived,
+	/// Forwarded HTLCs are only endorsed if they were given protected resources.
+	EndorseIfProtected,
+	/// Forwarded HTLCs are never endorsed.
+	NeverEndorse,
+}
+
+impl EndorsementPolicy {
+	/// Returns the endorsement signal to set on the outgoing HTLC for a forward that had the outcome provided.
+	/// HTLCs that will not be forwarded have no outgoing signal.
+	pub(crate) fn outgoing_endorsement(&self, incoming_endorsed: &Endorsement, forward_outcome: &ForwardOutcome) -> Endorsement {
+		if *forward_outcome == ForwardOutcome::ForwardOutcomeNoResources {
+			return Endorsement::EndorsementNone;
+		}
+
+		match self {
+			EndorsementPolicy::RelayAsReceived => return incoming_endorsed.clone(),
+			EndorsementPolicy::EndorseIfProtected => return Endorsement::new_endorsement_signal(
+				*forward_outcome == ForwardOutcome::ForwardOutcomeEndorsed),
+			EndorsementPolicy::NeverEndorse => return Endorsement::EndorsementFalse,
+		}
+	}
+}
+
+/// This selects the resource bucketer that tracks the resources of each outgoing channel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BucketerType {
+	/// Resources are tracked behind a lock by [`BucketResourceManager`], which supports per-incoming fair shares.
+	Locking,
+	/// Resources are reserved with atomic operations by [`AtomicBucketResourceManager`], so forwards over the
+	/// same outgoing channel don't wait for each other. Bucket configurations that limit incoming channels to a
+	/// fair share are not supported.
+	Atomic,
+}
 
 #[derive(Copy, Clone, Debug)]
-pub(crate) struct ManagerConfig {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManagerConfig {
 	/// Amount of time we examine the revenue of the outgoing links over.
-	revenue_window: Duration,
+	pub revenue_window: Duration,
 	/// Multiplier on revenue window that is used to determine the longer period of time
 	/// that incoming links reputation is assessed over.
-	reputation_multiplier: u8,
-	/// Percentage of liquidity and slots that are reserved for high reputation, endorsed HTLCs.
-	pub(crate) protected_percentage: u64,
+	pub reputation_multiplier: u8,
+	/// Percentage of liquidity and slots that are reserved for high reputation, endorsed HTLCs, when the
+	/// manager is created with the default bucket configuration.
+	pub protected_percentage: u64,
 	/// Amount of time that we reasonably expect HTLCs to complete within.
-	pub(crate) resolution_period: Duration,
+	pub resolution_period: Duration,
 	/// Expected block time.
-	pub(crate) block_time: Duration
+	pub block_time: Duration,
+	/// Whether the reputation of incoming channels with a known peer is shared by all of the peer's channels.
+	/// Peer reputation is assessed over the manager's reputation window, ignoring any channel overrides.
+	pub aggregate_peer_reputation: bool,
+	/// The endorsement signal that is set on forwarded HTLCs.
+	pub endorsement_policy: EndorsementPolicy,
+	/// The resource bucketer used for each outgoing channel.
+	pub bucketer: BucketerType,
 }
 
 impl Default for ManagerConfig {
@@ -36,6 +96,9 @@ impl Default for ManagerConfig {
 			protected_percentage: 50,
 			resolution_period: Duration::from_secs(90),
 			block_time: Duration::from_secs(60 * 10),
+			aggregate_peer_reputation: false,
+			endorsement_policy: EndorsementPolicy::EndorseIfProtected,
+			bucketer: BucketerType::Locking,
 		}
 	}
 }
@@ -51,102 +114,1565 @@ impl ManagerConfig {
 		if self.block_time == Duration::from_secs(0) {
 			return false;
 		}
+		if !valid_window(self.revenue_window) || self.reputation_multiplier == 0 {
+			return false;
+		}
 		return true;
 	}
 
-	fn reputation_window(&self) -> Duration {
-		return Duration::from_secs(self.revenue_window.as_secs() * self.reputation_multiplier as u64)
+	/// Returns the period of time that the reputation of incoming links is assessed over.
+	pub fn reputation_window(&self) -> Duration {
+		return Duration::from_secs(self.revenue_window.as_secs().saturating_mul(self.reputation_multiplier as u64))
+	}
+
+	/// Returns the revenue window used for a channel, which may override the manager's window.
+	fn channel_revenue_window(&self, chan_info: &ChannelInfo) -> Duration {
+		return chan_info.revenue_window.unwrap_or(self.revenue_window);
 	}
-		
+
+	/// Returns the reputation window used for a channel, which may override the manager's window.
+	fn channel_reputation_window(&self, chan_info: &ChannelInfo) -> Duration {
+		return chan_info.reputation_window.unwrap_or(self.reputation_window());
+	}
+}
+
+/// Decay rates are calculated over whole seconds, so windows must be at least a second long.
+fn valid_window(window: Duration) -> bool {
+	return window.as_secs() > 0;
+}
+
+/// Returns whether any window overrides set for a channel are valid.
+fn valid_channel_windows(chan_info: &ChannelInfo) -> bool {
+	return chan_info.revenue_window.is_none_or(valid_window) && chan_info.reputation_window.is_none_or(valid_window);
+}
+
+/// This reports the changes made when reconciling the manager's in-flight HTLCs with those known to the node.
+#[derive(Clone, Debug, Default)]
+pub struct ReconcileReport {
+	/// HTLCs that the manager did not track, and were registered as in flight.
+	pub restored: Vec<CircuitKey>,
+	/// Registered HTLCs that were forwarded with resources, but could not reoccupy them because their outgoing
+	/// channel's bucket is full. These HTLCs are tracked without resources.
+	pub unallocated: Vec<CircuitKey>,
+	/// HTLCs that the manager tracked as in flight, but are no longer known to the node.
+	pub removed: Vec<InFlightHTLC>,
+}
+
+/// This describes how HTLCs that are still in flight on a channel are treated when the channel closes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CloseTreatment {
+	/// HTLCs are resolved as failed, so their incoming channels' reputation is charged for any time that they
+	/// were held beyond the resolution period.
+	Fail,
+	/// HTLCs release their resources without affecting reputation or revenue.
+	Release,
+}
+
+/// This holds the final reputation and revenue of a channel that has been closed.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelArchive {
+	pub short_channel_id: ShortChannelId,
+	/// The time at which the channel was closed.
+	pub closed_at: SystemTime,
+	/// The routing restrictions of the channel when it was closed.
+	pub chan_info: ChannelInfo,
+	/// The channel's reputation when it was closed, if it was tracked as an incoming channel.
+	pub reputation: Option<f64>,
+	/// The channel's revenue when it was closed, if it was tracked as an outgoing channel.
+	pub revenue: Option<f64>,
 }
 
-pub struct ResourceManager<R: Deref>
-	where R::Target: ResourceBucketer
+pub struct ResourceManager<C: Deref + Clone>
+	where C::Target: Clock,
 {
 	manager_configuration: ManagerConfig,
 
-	// track channel reputation short chan id -> score (?)
+	/// Describes how the resources of every outgoing channel are split into buckets.
+	bucket_config: BucketConfig,
+
+	/// The routing restrictions of every channel registered with the manager, keyed by short channel id.
+	channels: HashMap<ShortChannelId, ChannelInfo>,
+
+	/// Tracks the reputation of channels that forward HTLCs to us, keyed by short channel id. Each tracker
+	/// is individually locked so that HTLCs on unrelated channels don't contend.
 	// TODO: make it a trait
-	channel_reputation: HashMap<u64, ReputationTracker>,
+	channel_reputation: HashMap<ShortChannelId, Mutex<ReputationTracker<C>>>,
+
+	/// Tracks the combined reputation of the incoming channels to each peer, keyed by node id, when reputation
+	/// is aggregated by peer. A peer's reputation is kept when its channels are removed.
+	peer_reputation: HashMap<NodeId, Mutex<ReputationTracker<C>>>,
 
+	/// Tracks the revenue and resources of channels that we forward HTLCs over, keyed by short channel id.
 	//TODO: make it a trait
-	target_channels: HashMap<u64, TargetChannelTracker<R>>,
+	target_channels: HashMap<ShortChannelId, TargetChannelTracker<ChannelBucketer, C>>,
+
+	/// Provides the current time to all of the manager's trackers.
+	clock: C,
 
-	resolution_period: Duration,
+	/// The height of the best block that the manager has been notified of, used to evaluate the risk of in-flight HTLCs.
+	block_height: u32,
 
-	block_time: Duration,
+	/// Records every forwarding decision and resolution made by the manager, if set.
+	audit_sink: Option<Arc<dyn AuditSink + Send + Sync>>,
+
+	/// The final state of channels that have been closed, keyed by short channel id.
+	archived_channels: HashMap<ShortChannelId, ChannelArchive>,
+
+	/// Maps the additional short channel ids that channels are known by, such as SCID aliases or the short
+	/// channel ids assigned by splices, to the short channel id that the channel was registered with. All state
+	/// is held under the registered short channel id, which is the channel's stable id.
+	channel_aliases: HashMap<ShortChannelId, ShortChannelId>,
 }
 
-impl<R: Deref> ResourceManager<R>	
-	where R::Target: ResourceBucketer
+impl<C: Deref + Clone> ResourceManager<C>
+	where C::Target: Clock,
 {
-	//TODO: add methods to generate scid's TargetChannelTracker / ReputationTracker
-	fn sufficient_reputation(&mut self, proposed_htlc: ProposedHTLC, outgoing_channel_revenue: f64) ->Result<ReputationCheck, ()> {
-
-		if let Some(channel_reputation_tracker) = self.channel_reputation.get_mut(&proposed_htlc.incoming_channel) {
-			
-			let reputation_check = ReputationCheck {
-				incoming_reputation: channel_reputation_tracker.incoming_reputation(),
-				outgoing_revenue: outgoing_channel_revenue,
-				htlc_risk: ReputationTracker::outstanding_risk(self.manager_configuration.block_time.as_secs() as f64, proposed_htlc.clone(), self.resolution_period)
-			};
-			return Ok(reputation_check);
+	/// Creates a manager that reserves the configured protected percentage of each outgoing channel's resources
+	/// for high reputation, endorsed HTLCs.
+	pub fn new(manager_configuration: ManagerConfig, clock: C) -> Result<Self, ErrResourceManager> {
+		if !manager_configuration.validate() {
+			return Err(ErrResourceManager::InvalidConfig);
 		}
-		return Err(())
+
+		let bucket_config = BucketConfig::protected(manager_configuration.protected_percentage);
+		return ResourceManager::with_bucket_config(manager_configuration, bucket_config, clock);
 	}
-}
 
-impl<R: Deref>LocalResourceManager for ResourceManager<R>
-	where R::Target: ResourceBucketer
-{	
-	fn forward_htlc(&mut self, proposed_htlc: ProposedHTLC, chan_info: ChannelInfo) -> Result<ForwardDecision, ()>
-	{
-		if proposed_htlc.outgoing_amount_msat > MAX_MILLI_SATOSHI {
-			return Err(())
+	/// Creates a manager that splits each outgoing channel's resources into the buckets provided.
+	pub fn with_bucket_config(manager_configuration: ManagerConfig, bucket_config: BucketConfig, clock: C) -> Result<Self, ErrResourceManager> {
+		if !manager_configuration.validate() || bucket_config.validate().is_err() {
+			return Err(ErrResourceManager::InvalidConfig);
+		}
+
+		if manager_configuration.bucketer == BucketerType::Atomic &&
+			bucket_config.buckets.iter().any(|tier| tier.fair_share_percentage.is_some()) {
+			return Err(ErrResourceManager::InvalidConfig);
+		}
+
+		return Ok(ResourceManager {
+			manager_configuration,
+			bucket_config,
+			channels: HashMap::new(),
+			channel_reputation: HashMap::new(),
+			peer_reputation: HashMap::new(),
+			target_channels: HashMap::new(),
+			clock,
+			block_height: 0,
+			audit_sink: None,
+			archived_channels: HashMap::new(),
+			channel_aliases: HashMap::new(),
+		});
+	}
+
+	/// Rebuilds a manager from a snapshot of its state. Reputation and revenue are decayed for the time that
+	/// has passed since the snapshot was taken, and HTLCs that were in flight reoccupy their resources.
+	pub fn from_snapshot(snapshot: ResourceManagerSnapshot, clock: C) -> Result<Self, ErrResourceManager> {
+		let mut resource_manager = ResourceManager::with_bucket_config(snapshot.manager_config, snapshot.bucket_config, clock)?;
+		resource_manager.block_height = snapshot.block_height;
+		resource_manager.archived_channels = snapshot.archived_channels.into_iter()
+			.map(|archive| (archive.short_channel_id, archive))
+			.collect();
+		resource_manager.channel_aliases = snapshot.channel_aliases.into_iter().collect();
+
+		for peer in snapshot.peers.iter() {
+			let reputation_tracker = resource_manager.new_peer_reputation_tracker(peer.reputation.revenue.clone());
+			resource_manager.peer_reputation.insert(peer.node_id, Mutex::new(reputation_tracker));
+		}
+
+		for channel in snapshot.channels.iter() {
+			if resource_manager.channels.contains_key(&channel.short_channel_id) {
+				return Err(ErrResourceManager::ChannelExists(channel.short_channel_id));
+			}
+
+			if let Some(ref revenue) = channel.target_revenue {
+				let target_channel_tracker = resource_manager.new_target_channel(channel.short_channel_id, channel.chan_info, revenue.clone())?;
+				resource_manager.target_channels.insert(channel.short_channel_id, target_channel_tracker);
+			}
+
+			if let Some(ref reputation) = channel.reputation {
+				let reputation_tracker = resource_manager.new_reputation_tracker(channel.chan_info, reputation.revenue.clone());
+				resource_manager.channel_reputation.insert(channel.short_channel_id, Mutex::new(reputation_tracker));
+			}
+
+			resource_manager.channels.insert(channel.short_channel_id, channel.chan_info);
 		}
 
-		if let Some(channel_reputation_tracker) = self.channel_reputation.get_mut(&proposed_htlc.incoming_channel) {
-			if let Some(target_channel_tracker) = self.target_channels.get_mut(&proposed_htlc.incoming_channel) {
-				let forward_decision = target_channel_tracker.add_inflight(channel_reputation_tracker.incoming_reputation(), proposed_htlc.clone()).unwrap();
+		// In-flight HTLCs are restored once all channels are known, as they may occupy resources on any outgoing channel.
+		let in_flight_htlcs = snapshot.channels.into_iter().filter_map(|channel| channel.reputation)
+			.chain(snapshot.peers.into_iter().map(|peer| peer.reputation))
+			.flat_map(|reputation| reputation.in_flight_htlcs);
 
-				if channel_reputation_tracker.add_inflight(proposed_htlc.clone(), forward_decision.clone().forward_outcome).is_err() {
-					return Err(())
+		for in_flight_htlc in in_flight_htlcs {
+			let outgoing_channel = in_flight_htlc.proposed_htlc.outgoing_channel;
+			if in_flight_htlc.bucket.is_some() {
+				let target_channel_tracker = resource_manager.target_channels.get(&outgoing_channel)
+					.ok_or(ErrResourceManager::ChannelNotFound(outgoing_channel))?;
+
+				if !target_channel_tracker.restore_inflight(&in_flight_htlc) {
+					return Err(ErrResourceManager::InsufficientResources(outgoing_channel));
 				}
-				return Ok(forward_decision);
 			}
+
+			resource_manager.lock_reputation(in_flight_htlc.proposed_htlc.incoming_channel)?.restore_inflight(in_flight_htlc);
 		}
-		return Err(())
+
+		return Ok(resource_manager);
 	}
-	fn resolve_htlc(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ()> {
 
-		if let Some(channel_reputation_tracker) = self.channel_reputation.get_mut(&resolved_htlc.incoming_channel) {
-			let in_flight_ret = channel_reputation_tracker.resolve_inflight(resolved_htlc.clone());
-			if in_flight_ret.is_err() { return Err(()) }
-			
-			let in_flight = in_flight_ret.unwrap();
+	/// Reconciles the HTLCs that the manager tracks as in flight with the HTLCs that the node still has locked in
+	/// on its commitments, for example after a restart.
+	///
+	/// HTLCs that the manager does not track are registered with their original add time and outcome, and
+	/// reoccupy resources on their outgoing channel. HTLCs that the manager tracks but the node does not are
+	/// removed without affecting reputation or revenue. No state is changed if any HTLC is invalid.
+	///
+	/// HTLCs may refer to their channels by any of the short channel ids that they are known by.
+	pub fn reconcile<I: Iterator<Item = InFlightHTLC>>(&mut self, in_flight: I) -> Result<ReconcileReport, ErrResourceManager> {
+		let live_htlcs: Vec<InFlightHTLC> = in_flight.map(|in_flight_htlc| InFlightHTLC {
+			proposed_htlc: self.proposed_htlc_by_id(in_flight_htlc.proposed_htlc),
+			..in_flight_htlc
+		}).collect();
 
-			if in_flight.outgoing_decision == ForwardOutcome::ForwardOutcomeNoResources { return Err(()) }
+		let mut live_circuits = HashSet::with_capacity(live_htlcs.len());
+		for in_flight_htlc in live_htlcs.iter() {
+			let proposed_htlc = &in_flight_htlc.proposed_htlc;
+			check_amount(proposed_htlc)?;
+			if !self.channels.get(&proposed_htlc.incoming_channel).is_some_and(|chan_info| chan_info.direction.incoming()) {
+				return Err(ErrResourceManager::UnknownIncomingChannel(proposed_htlc.incoming_channel));
+			}
+			if !self.target_channels.contains_key(&proposed_htlc.outgoing_channel) {
+				return Err(ErrResourceManager::UnknownOutgoingChannel(proposed_htlc.outgoing_channel));
+			}
+			if !live_circuits.insert(proposed_htlc.incoming_circuit()) {
+				return Err(ErrResourceManager::Reputation {
+					incoming_channel: proposed_htlc.incoming_channel,
+					incoming_index: proposed_htlc.incoming_index,
+					err: ErrReputation::DuplicateHTLC,
+				});
+			}
+		}
+
+		let mut report = ReconcileReport::default();
 
-			if in_flight.proposed_htlc.outgoing_channel != resolved_htlc.outgoing_channel { return Err(()) }
+		for short_channel_id in self.incoming_channels() {
+			let mut reputation_tracker = self.lock_reputation(short_channel_id)?;
 
-			if let Some(target_channel_tracker) = self.target_channels.get_mut(&in_flight.proposed_htlc.outgoing_channel) {
-				let ret = target_channel_tracker.resolve_inflight(resolved_htlc.clone(), in_flight.clone());
-				if ret.is_err() { return Err(()) }
+			for in_flight_htlc in reputation_tracker.in_flight_htlcs() {
+				let incoming_circuit = in_flight_htlc.incoming_circuit();
+				if incoming_circuit.short_channel_id != short_channel_id || live_circuits.contains(&incoming_circuit) {
+					continue;
+				}
+
+				let outgoing_channel = in_flight_htlc.proposed_htlc.outgoing_channel;
+				if let Some(target_channel_tracker) = self.target_channels.get(&outgoing_channel) {
+					target_channel_tracker.cancel_inflight(&in_flight_htlc)?;
+				}
+
+				reputation_tracker.cancel_inflight(&incoming_circuit);
+				report.removed.push(in_flight_htlc);
+			}
+		}
+
+		for mut in_flight_htlc in live_htlcs.into_iter() {
+			let incoming_circuit = in_flight_htlc.incoming_circuit();
+			let outgoing_channel = in_flight_htlc.proposed_htlc.outgoing_channel;
+
+			let mut reputation_tracker = self.lock_reputation(incoming_circuit.short_channel_id)?;
+
+			if reputation_tracker.get_inflight(&incoming_circuit).is_some() {
+				continue;
 			}
-			return Ok(in_flight);
+
+			in_flight_htlc.bucket = match reconciled_class(&in_flight_htlc) {
+				Some(htlc_class) => {
+					let target_channel_tracker = self.target_channels.get(&outgoing_channel)
+						.ok_or(ErrResourceManager::UnknownOutgoingChannel(outgoing_channel))?;
+					target_channel_tracker.reserve_inflight(htlc_class, &in_flight_htlc.proposed_htlc)
+				},
+				None => None,
+			};
+
+			if in_flight_htlc.bucket.is_none() && in_flight_htlc.outgoing_decision != ForwardOutcome::ForwardOutcomeNoResources {
+				report.unallocated.push(incoming_circuit);
+			}
+
+			reputation_tracker.restore_inflight(in_flight_htlc);
+			report.restored.push(incoming_circuit);
+		}
+
+		return Ok(report);
+	}
+
+	/// Returns a snapshot of the manager's state that can be persisted and later restored with `from_snapshot`.
+	pub fn snapshot(&self) -> Result<ResourceManagerSnapshot, ErrResourceManager> {
+		let mut channels = Vec::with_capacity(self.channels.len());
+
+		for (short_channel_id, chan_info) in self.channels.iter() {
+			let reputation = match self.channel_reputation.get(short_channel_id) {
+				Some(reputation_tracker) => {
+					let reputation_tracker = lock(*short_channel_id, reputation_tracker)?;
+					Some(ReputationSnapshot {
+						revenue: reputation_tracker.revenue_snapshot(),
+						in_flight_htlcs: reputation_tracker.in_flight_htlcs(),
+					})
+				},
+				None => None,
+			};
+
+			let target_revenue = self.target_channels.get(short_channel_id)
+				.map(|target_channel_tracker| target_channel_tracker.revenue_snapshot());
+
+			channels.push(ChannelSnapshot {
+				short_channel_id: *short_channel_id,
+				chan_info: *chan_info,
+				reputation,
+				target_revenue,
+			});
 		}
-		return Err(())
+
+		let mut peers = Vec::with_capacity(self.peer_reputation.len());
+		for (node_id, reputation_tracker) in self.peer_reputation.iter() {
+			let reputation_tracker = lock_peer(*node_id, reputation_tracker)?;
+			peers.push(PeerSnapshot {
+				node_id: *node_id,
+				reputation: ReputationSnapshot {
+					revenue: reputation_tracker.revenue_snapshot(),
+					in_flight_htlcs: reputation_tracker.in_flight_htlcs(),
+				},
+			});
+		}
+
+		return Ok(ResourceManagerSnapshot {
+			manager_config: self.manager_configuration,
+			bucket_config: self.bucket_config.clone(),
+			block_height: self.block_height,
+			channels,
+			peers,
+			channel_aliases: self.channel_aliases.iter().map(|(alias, channel_id)| (*alias, *channel_id)).collect(),
+			archived_channels: self.archived_channels.values().cloned().collect(),
+		});
+	}
+
+	/// Registers a channel with the manager, creating reputation tracking if it may be used as an incoming
+	/// channel and resource bucketing if it may be used as an outgoing channel.
+	pub fn add_channel(&mut self, short_channel_id: ShortChannelId, chan_info: ChannelInfo) -> Result<(), ErrResourceManager> {
+		if self.channels.contains_key(&short_channel_id) || self.channel_aliases.contains_key(&short_channel_id) {
+			return Err(ErrResourceManager::ChannelExists(short_channel_id));
+		}
+
+		if !valid_channel_windows(&chan_info) {
+			return Err(ErrResourceManager::InvalidChannelInfo(short_channel_id));
+		}
+
+		// Create the target first, as it is the only part of registration that may fail.
+		if chan_info.direction.outgoing() {
+			let target_channel_tracker = self.new_target_channel(short_channel_id, chan_info, self.new_decaying_average_start())?;
+			self.target_channels.insert(short_channel_id, target_channel_tracker);
+		}
+
+		if chan_info.direction.incoming() {
+			self.add_reputation(short_channel_id, chan_info);
+		}
+
+		self.channels.insert(short_channel_id, chan_info);
+
+		return Ok(());
+	}
+
+	/// Registers an additional short channel id that a channel is known by, such as a SCID alias used before the
+	/// channel confirms, or the short channel id that it is assigned once a splice confirms. HTLCs that use
+	/// either short channel id share the channel's reputation, revenue and resources, including HTLCs that are
+	/// already in flight.
+	pub fn announce_short_channel_id(&mut self, short_channel_id: ShortChannelId, new_short_channel_id: ShortChannelId) -> Result<(), ErrResourceManager> {
+		let channel_id = match self.channel_id(short_channel_id) {
+			Some(channel_id) => channel_id,
+			None => return Err(ErrResourceManager::ChannelNotFound(short_channel_id)),
+		};
+
+		if self.resolve_channel_id(new_short_channel_id) == channel_id {
+			return Ok(());
+		}
+
+		if self.channels.contains_key(&new_short_channel_id) || self.channel_aliases.contains_key(&new_short_channel_id) {
+			return Err(ErrResourceManager::ChannelExists(new_short_channel_id));
+		}
+
+		self.channel_aliases.insert(new_short_channel_id, channel_id);
+
+		return Ok(());
+	}
+
+	/// Returns the stable id that a channel's state is held under, which is the short channel id that it was
+	/// registered with, if the short channel id provided refers to a registered channel.
+	pub fn channel_id(&self, short_channel_id: ShortChannelId) -> Option<ShortChannelId> {
+		let channel_id = self.resolve_channel_id(short_channel_id);
+		return self.channels.contains_key(&channel_id).then_some(channel_id);
+	}
+
+	/// Removes all state that the manager holds for a channel, including any additional short channel ids that it
+	/// is known by. If the channel shares its peer's reputation, the peer's reputation is kept for its other
+	/// channels.
+	///
+	/// HTLCs in flight to or from the channel are released without affecting reputation or revenue, so that they
+	/// don't hold resources on, or count towards the risk of, the other channel in their circuit.
+	pub fn remove_channel(&mut self, short_channel_id: ShortChannelId) -> Result<(), ErrResourceManager> {
+		let short_channel_id = self.resolve_channel_id(short_channel_id);
+		let chan_info = match self.channels.get(&short_channel_id) {
+			Some(chan_info) => *chan_info,
+			None => return Err(ErrResourceManager::ChannelNotFound(short_channel_id)),
+		};
+
+		for in_flight_htlc in self.channel_in_flight_htlcs(short_channel_id)? {
+			self.cancel_forward_shared(in_flight_htlc.proposed_htlc.incoming_channel, in_flight_htlc.proposed_htlc.incoming_index)?;
+		}
+
+		self.remove_reputation(short_channel_id, chan_info)?;
+		self.target_channels.remove(&short_channel_id);
+		self.channels.remove(&short_channel_id);
+		self.channel_aliases.retain(|_, channel_id| *channel_id != short_channel_id);
+
+		return Ok(());
+	}
+
+	/// Closes a channel, resolving every HTLC that is in flight to or from it with the treatment provided and
+	/// releasing the resources that they occupy on the other channel in their circuit. The channel's final
+	/// reputation and revenue are archived under its stable id, and all other state for it is removed. Returns the
+	/// HTLCs that were resolved.
+	pub fn close_channel(&mut self, short_channel_id: ShortChannelId, treatment: CloseTreatment) -> Result<Vec<InFlightHTLC>, ErrResourceManager> {
+		let short_channel_id = self.resolve_channel_id(short_channel_id);
+		let chan_info = match self.channels.get(&short_channel_id) {
+			Some(chan_info) => *chan_info,
+			None => return Err(ErrResourceManager::ChannelNotFound(short_channel_id)),
+		};
+
+		let in_flight_htlcs = self.channel_in_flight_htlcs(short_channel_id)?;
+		let mut resolved_htlcs = Vec::with_capacity(in_flight_htlcs.len());
+		for in_flight_htlc in in_flight_htlcs.into_iter() {
+			let proposed_htlc = &in_flight_htlc.proposed_htlc;
+			let resolved_htlc = match treatment {
+				CloseTreatment::Fail => self.resolve_htlc_shared(ResolvedHTLC::new(self.clock.now(), proposed_htlc.incoming_channel,
+					proposed_htlc.incoming_index, proposed_htlc.outgoing_channel, in_flight_htlc.outgoing_index.unwrap_or(0), false))?,
+				CloseTreatment::Release => self.cancel_forward_shared(proposed_htlc.incoming_channel, proposed_htlc.incoming_index)?,
+			};
+			resolved_htlcs.push(resolved_htlc);
+		}
+
+		let reputation = match chan_info.direction.incoming() {
+			true => Some(self.lock_reputation(short_channel_id)?.incoming_reputation(self.block_height).incoming_revenue),
+			false => None,
+		};
+
+		let revenue = self.target_channels.get(&short_channel_id)
+			.map(|target_channel_tracker| target_channel_tracker.revenue_value());
+
+		self.remove_channel(short_channel_id)?;
+		self.archived_channels.insert(short_channel_id, ChannelArchive {
+			short_channel_id,
+			closed_at: self.clock.now(),
+			chan_info,
+			reputation,
+			revenue,
+		});
+
+		return Ok(resolved_htlcs);
+	}
+
+	/// Returns the HTLCs in flight to or from a channel, identified by its stable id.
+	fn channel_in_flight_htlcs(&self, short_channel_id: ShortChannelId) -> Result<Vec<InFlightHTLC>, ErrResourceManager> {
+		let mut in_flight_htlcs = Vec::new();
+		for incoming_channel in self.incoming_channels() {
+			in_flight_htlcs.extend(self.lock_reputation(incoming_channel)?.in_flight_htlcs().into_iter().filter(|in_flight_htlc|
+				in_flight_htlc.proposed_htlc.incoming_channel == incoming_channel &&
+				(incoming_channel == short_channel_id || in_flight_htlc.proposed_htlc.outgoing_channel == short_channel_id)));
+		}
+
+		return Ok(in_flight_htlcs);
+	}
+
+	/// Returns the final state of a channel that has been closed, if any.
+	pub fn archived_channel(&self, short_channel_id: ShortChannelId) -> Option<&ChannelArchive> {
+		return self.archived_channels.get(&short_channel_id);
+	}
+
+	/// Sets the sink that every subsequent forwarding decision and resolution is recorded to.
+	pub fn set_audit_sink(&mut self, audit_sink: Arc<dyn AuditSink + Send + Sync>) {
+		self.audit_sink = Some(audit_sink);
+	}
+
+	/// Updates the manager with the height of the best block, which is used to evaluate the risk of in-flight
+	/// HTLCs by the number of blocks remaining until they expire. This should be called on startup and for every
+	/// new block. Heights below the current height, such as those seen during a reorganization, are ignored so
+	/// that the risk of in-flight HTLCs is never overstated by a stale height.
+	///
+	/// HTLCs restored from a snapshot that did not record their expiry are taken to expire their expiry delta
+	/// after the first height provided.
+	pub fn update_block_height(&mut self, block_height: u32) -> Result<(), ErrResourceManager> {
+		if block_height < self.block_height {
+			return Ok(());
+		}
+
+		for (short_channel_id, channel_reputation_tracker) in self.channel_reputation.iter_mut() {
+			channel_reputation_tracker.get_mut().map_err(|_| ErrResourceManager::ChannelLockPoisoned(*short_channel_id))?
+				.set_unknown_expiries(block_height);
+		}
+		for (node_id, peer_reputation_tracker) in self.peer_reputation.iter_mut() {
+			peer_reputation_tracker.get_mut().map_err(|_| ErrResourceManager::PeerLockPoisoned(*node_id))?
+				.set_unknown_expiries(block_height);
+		}
+
+		self.block_height = block_height;
+		return Ok(());
+	}
+
+	/// Updates the roles and resource limits of a registered channel. State is kept for any role that the
+	/// channel retains, is created for new roles and dropped for roles that it no longer has.
+	///
+	/// If the limits of an outgoing channel change, for example because its peer changed the HTLCs it accepts
+	/// or the channel was spliced, its buckets are resized without evicting HTLCs that are in flight. Note that
+	/// a channel's window overrides and peer are fixed when it is registered.
+	pub fn update_channel(&mut self, short_channel_id: ShortChannelId, chan_info: ChannelInfo) -> Result<(), ErrResourceManager> {
+		let short_channel_id = self.resolve_channel_id(short_channel_id);
+		let current_info = match self.channels.get(&short_channel_id) {
+			Some(current_info) => *current_info,
+			None => return Err(ErrResourceManager::ChannelNotFound(short_channel_id)),
+		};
+
+		let updated_info = ChannelInfo {
+			revenue_window: current_info.revenue_window,
+			reputation_window: current_info.reputation_window,
+			peer: current_info.peer,
+			..chan_info
+		};
+
+		let limits_changed = chan_info.in_flight_liquidity_limit != current_info.in_flight_liquidity_limit ||
+			chan_info.in_flight_htlc_limit != current_info.in_flight_htlc_limit;
+
+		if chan_info.direction.outgoing() {
+			match self.target_channels.get(&short_channel_id) {
+				Some(target_channel) if limits_changed => {
+					target_channel
+						.resize_buckets(chan_info.in_flight_liquidity_limit, chan_info.in_flight_htlc_limit)
+						.map_err(|err| ErrResourceManager::Bucketing { outgoing_channel: short_channel_id, err })?;
+				},
+				Some(_) => {},
+				None => {
+					let target_channel_tracker = self.new_target_channel(short_channel_id, updated_info, self.new_decaying_average_start())?;
+					self.target_channels.insert(short_channel_id, target_channel_tracker);
+				},
+			}
+		} else {
+			self.target_channels.remove(&short_channel_id);
+		}
+
+		if chan_info.direction.incoming() && !current_info.direction.incoming() {
+			self.add_reputation(short_channel_id, updated_info);
+		} else if !chan_info.direction.incoming() && current_info.direction.incoming() {
+			self.remove_reputation(short_channel_id, current_info)?;
+		}
+
+		self.channels.insert(short_channel_id, updated_info);
+
+		return Ok(());
+	}
+
+	fn new_decaying_average_start(&self) -> DecayingAverageStart {
+		return DecayingAverageStart {
+			last_update: self.clock.now(),
+			value: 0.0,
+		};
+	}
+
+	fn new_target_channel(&self, short_channel_id: ShortChannelId, chan_info: ChannelInfo, start_value: DecayingAverageStart) -> Result<TargetChannelTracker<ChannelBucketer, C>, ErrResourceManager> {
+		let bucketing_err = |err| ErrResourceManager::Bucketing { outgoing_channel: short_channel_id, err };
+		let (liquidity_limit, htlc_limit) = (chan_info.in_flight_liquidity_limit, chan_info.in_flight_htlc_limit);
+
+		let resource_buckets: ChannelBucketer = match self.manager_configuration.bucketer {
+			BucketerType::Locking => Box::new(BucketResourceManager::with_config(liquidity_limit, htlc_limit,
+				self.bucket_config.clone()).map_err(bucketing_err)?),
+			BucketerType::Atomic => Box::new(AtomicBucketResourceManager::with_config(liquidity_limit, htlc_limit,
+				self.bucket_config.clone()).map_err(bucketing_err)?),
+		};
+
+		return Ok(TargetChannelTracker::new(self.manager_configuration, self.manager_configuration.channel_revenue_window(&chan_info),
+			start_value, resource_buckets, self.clock.clone()));
+	}
+
+	fn new_reputation_tracker(&self, chan_info: ChannelInfo, start_value: DecayingAverageStart) -> ReputationTracker<C> {
+		return ReputationTracker::new(self.manager_configuration, self.manager_configuration.channel_reputation_window(&chan_info),
+			start_value, self.clock.clone());
+	}
+
+	fn new_peer_reputation_tracker(&self, start_value: DecayingAverageStart) -> ReputationTracker<C> {
+		return ReputationTracker::new(self.manager_configuration, self.manager_configuration.reputation_window(),
+			start_value, self.clock.clone());
+	}
+
+	/// Starts tracking the reputation of an incoming channel, using its peer's reputation if it is shared.
+	fn add_reputation(&mut self, short_channel_id: ShortChannelId, chan_info: ChannelInfo) {
+		match self.reputation_peer(&chan_info) {
+			Some(node_id) => if !self.peer_reputation.contains_key(&node_id) {
+				let reputation_tracker = self.new_peer_reputation_tracker(self.new_decaying_average_start());
+				self.peer_reputation.insert(node_id, Mutex::new(reputation_tracker));
+			},
+			None => {
+				let reputation_tracker = self.new_reputation_tracker(chan_info, self.new_decaying_average_start());
+				self.channel_reputation.insert(short_channel_id, Mutex::new(reputation_tracker));
+			},
+		}
+	}
+
+	/// Stops tracking the reputation of an incoming channel. Only the channel's in-flight HTLCs are removed from
+	/// its peer's reputation, as it is shared with the peer's other channels.
+	fn remove_reputation(&mut self, short_channel_id: ShortChannelId, chan_info: ChannelInfo) -> Result<(), ErrResourceManager> {
+		match self.reputation_peer(&chan_info).and_then(|node_id| self.peer_reputation.get(&node_id).map(|tracker| (node_id, tracker))) {
+			Some((node_id, reputation_tracker)) => lock_peer(node_id, reputation_tracker)?.remove_channel_inflight(short_channel_id),
+			None => { self.channel_reputation.remove(&short_channel_id); },
+		}
+
+		return Ok(());
 	}
 }
 
+impl<C: Deref + Clone> ResourceManager<C>
+	where C::Target: Clock,
+{
+	/// Returns the stable id of the channel that a short channel id refers to, which is the short channel id
+	/// itself unless it is an additional short channel id of a registered channel.
+	fn resolve_channel_id(&self, short_channel_id: ShortChannelId) -> ShortChannelId {
+		return *self.channel_aliases.get(&short_channel_id).unwrap_or(&short_channel_id);
+	}
+
+	/// Returns the HTLC with its channels identified by their stable ids.
+	fn proposed_htlc_by_id(&self, proposed_htlc: ProposedHTLC) -> ProposedHTLC {
+		return ProposedHTLC {
+			incoming_channel: self.resolve_channel_id(proposed_htlc.incoming_channel),
+			outgoing_channel: self.resolve_channel_id(proposed_htlc.outgoing_channel),
+			..proposed_htlc
+		};
+	}
+
+	/// Returns the resolution with its channels identified by their stable ids.
+	fn resolved_htlc_by_id(&self, resolved_htlc: ResolvedHTLC) -> ResolvedHTLC {
+		return ResolvedHTLC {
+			incoming_channel: self.resolve_channel_id(resolved_htlc.incoming_channel),
+			outgoing_channel: self.resolve_channel_id(resolved_htlc.outgoing_channel),
+			..resolved_htlc
+		};
+	}
+
+	/// Returns the peer whose reputation a channel shares, if the manager aggregates reputation by peer.
+	fn reputation_peer(&self, chan_info: &ChannelInfo) -> Option<NodeId> {
+		if !self.manager_configuration.aggregate_peer_reputation || !chan_info.direction.incoming() {
+			return None;
+		}
+
+		return chan_info.peer;
+	}
+
+	/// Returns the short channel ids of every channel registered as an incoming channel.
+	fn incoming_channels(&self) -> impl Iterator<Item = ShortChannelId> + '_ {
+		return self.channels.iter().filter(|(_, chan_info)| chan_info.direction.incoming()).map(|(short_channel_id, _)| *short_channel_id);
+	}
+
+	/// Locks the tracker that holds an incoming channel's reputation, which is its peer's tracker if the
+	/// channel shares its peer's reputation.
+	fn lock_reputation(&self, incoming_channel: ShortChannelId) -> Result<MutexGuard<'_, ReputationTracker<C>>, ErrResourceManager> {
+		let peer = self.channels.get(&incoming_channel).and_then(|chan_info| self.reputation_peer(chan_info));
+
+		return match peer {
+			Some(node_id) => match self.peer_reputation.get(&node_id) {
+				Some(reputation_tracker) => lock_peer(node_id, reputation_tracker),
+				None => Err(ErrResourceManager::UnknownIncomingChannel(incoming_channel)),
+			},
+			None => match self.channel_reputation.get(&incoming_channel) {
+				Some(reputation_tracker) => lock(incoming_channel, reputation_tracker),
+				None => Err(ErrResourceManager::UnknownIncomingChannel(incoming_channel)),
+			},
+		};
+	}
+
+	/// Returns the decision that forwarding the HTLC provided would currently result in, without reserving
+	/// resources for it or tracking it as in flight.
+	pub fn evaluate_htlc(&self, proposed_htlc: &ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager> {
+		check_amount(proposed_htlc)?;
+		let proposed_htlc = &self.proposed_htlc_by_id(proposed_htlc.clone());
+
+		let target_channel_tracker = self.target_channels.get(&proposed_htlc.outgoing_channel)
+			.ok_or(ErrResourceManager::UnknownOutgoingChannel(proposed_htlc.outgoing_channel))?;
+
+		let mut channel_reputation_tracker = self.lock_reputation(proposed_htlc.incoming_channel)?;
+
+		return Ok(target_channel_tracker.evaluate_htlc(channel_reputation_tracker.incoming_reputation(self.block_height),
+			proposed_htlc));
+	}
+
+	/// Forwards a HTLC while only locking the tracker of its incoming channel, so that it can be called
+	/// concurrently for HTLCs from other incoming channels. The outgoing channel's resources are reserved by its
+	/// bucketer, which only locks them if it is a [`BucketerType::Locking`] bucketer. The HTLC is tracked with its
+	/// channels identified by their stable ids.
+	pub(crate) fn forward_htlc_shared(&self, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager> {
+		check_amount(&proposed_htlc)?;
+		let proposed_htlc = self.proposed_htlc_by_id(proposed_htlc);
+
+		let target_channel_tracker = self.target_channels.get(&proposed_htlc.outgoing_channel)
+			.ok_or(ErrResourceManager::UnknownOutgoingChannel(proposed_htlc.outgoing_channel))?;
+
+		let mut channel_reputation_tracker = self.lock_reputation(proposed_htlc.incoming_channel)?;
+
+		// Duplicates are rejected before any resources are reserved for them.
+		if channel_reputation_tracker.get_inflight(&proposed_htlc.incoming_circuit()).is_some() {
+			return Err(ErrResourceManager::Reputation {
+				incoming_channel: proposed_htlc.incoming_channel,
+				incoming_index: proposed_htlc.incoming_index,
+				err: ErrReputation::DuplicateHTLC,
+			});
+		}
+
+		let forward_decision = target_channel_tracker.add_inflight(channel_reputation_tracker.incoming_reputation(self.block_height),
+			proposed_htlc.clone())?;
+
+		channel_reputation_tracker.add_inflight(proposed_htlc.clone(), &forward_decision)?;
+
+		if let Some(ref audit_sink) = self.audit_sink {
+			audit_sink.record(&AuditEvent::Forward(ForwardAudit {
+				timestamp: self.clock.now(),
+				proposed_htlc,
+				decision: forward_decision.clone(),
+				bucket_occupancy: target_channel_tracker.bucket_occupancy(),
+			}));
+		}
+
+		return Ok(forward_decision);
+	}
+
+	/// Resolves a HTLC while only locking the tracker of its incoming channel, so that it can be called
+	/// concurrently for HTLCs from other incoming channels.
+	pub(crate) fn resolve_htlc_shared(&self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrResourceManager> {
+		let resolved_htlc = self.resolved_htlc_by_id(resolved_htlc);
+		let mut channel_reputation_tracker = self.lock_reputation(resolved_htlc.incoming_channel)?;
+
+		// Validate the resolution before any state is updated.
+		let in_flight = match channel_reputation_tracker.get_inflight(&resolved_htlc.incoming_circuit()) {
+			Some(in_flight) => in_flight.clone(),
+			None => return Err(ErrResourceManager::Reputation {
+				incoming_channel: resolved_htlc.incoming_channel,
+				incoming_index: resolved_htlc.incoming_index,
+				err: ErrReputation::ResolutionNotFound,
+			}),
+		};
+
+		let outgoing_channel = in_flight.proposed_htlc.outgoing_channel;
+		if outgoing_channel != resolved_htlc.outgoing_channel {
+			return Err(ErrResourceManager::OutgoingChannelMismatch {
+				incoming_channel: resolved_htlc.incoming_channel,
+				incoming_index: resolved_htlc.incoming_index,
+				expected: outgoing_channel,
+				actual: resolved_htlc.outgoing_channel,
+			});
+		}
+
+		// The outgoing index is only validated if it was assigned, as HTLCs that fail locally never have one.
+		if let Some(expected) = in_flight.outgoing_index {
+			if expected != resolved_htlc.outgoing_index {
+				return Err(ErrResourceManager::OutgoingIndexMismatch {
+					incoming_channel: resolved_htlc.incoming_channel,
+					incoming_index: resolved_htlc.incoming_index,
+					expected,
+					actual: resolved_htlc.outgoing_index,
+				});
+			}
+		}
+
+		let effective_fees = channel_reputation_tracker.effective_fees(self.manager_configuration.resolution_period,
+			resolved_htlc.timestamp_settled, in_flight, resolved_htlc.success);
+
+		// The incoming channel's reputation is updated first, as it leaves the HTLC in flight if it fails. Once it
+		// has been removed, the outgoing channel releases the HTLC's resources even if it can't credit its fees.
+		let in_flight = channel_reputation_tracker.resolve_inflight(resolved_htlc.clone())?;
+
+		// HTLCs that were not given resources were never added to the outgoing channel.
+		if in_flight.bucket.is_some() {
+			if let Some(target_channel_tracker) = self.target_channels.get(&outgoing_channel) {
+				target_channel_tracker.resolve_inflight(resolved_htlc.clone(), in_flight.clone())?;
+			}
+		}
+
+		if let Some(ref audit_sink) = self.audit_sink {
+			audit_sink.record(&AuditEvent::Resolve(ResolveAudit {
+				resolved_htlc,
+				in_flight_htlc: in_flight.clone(),
+				effective_fees,
+			}));
+		}
+
+		return Ok(in_flight);
+	}
+
+	pub(crate) fn assign_outgoing_index_shared(&self, incoming_channel: ShortChannelId, incoming_index: u32, outgoing_index: u32) -> Result<(), ErrResourceManager> {
+		let incoming_channel = self.resolve_channel_id(incoming_channel);
+		let incoming_circuit = CircuitKey { short_channel_id: incoming_channel, htlc_index: incoming_index };
+		return self.lock_reputation(incoming_channel)?.assign_outgoing_index(&incoming_circuit, outgoing_index);
+	}
+
+	pub(crate) fn cancel_forward_shared(&self, incoming_channel: ShortChannelId, incoming_index: u32) -> Result<InFlightHTLC, ErrResourceManager> {
+		let incoming_channel = self.resolve_channel_id(incoming_channel);
+		let mut channel_reputation_tracker = self.lock_reputation(incoming_channel)?;
+
+		let incoming_circuit = CircuitKey { short_channel_id: incoming_channel, htlc_index: incoming_index };
+		let in_flight = match channel_reputation_tracker.get_inflight(&incoming_circuit) {
+			Some(in_flight) => in_flight.clone(),
+			None => return Err(ErrResourceManager::Reputation {
+				incoming_channel,
+				incoming_index,
+				err: ErrReputation::ResolutionNotFound,
+			}),
+		};
+
+		// Resources are released before the HTLC is removed, so that it is still tracked if this fails.
+		let outgoing_channel = in_flight.proposed_htlc.outgoing_channel;
+		if let Some(target_channel_tracker) = self.target_channels.get(&outgoing_channel) {
+			target_channel_tracker.cancel_inflight(&in_flight)?;
+		}
+
+		channel_reputation_tracker.cancel_inflight(&incoming_circuit);
+
+		if let Some(ref audit_sink) = self.audit_sink {
+			audit_sink.record(&AuditEvent::Cancel(CancelAudit {
+				timestamp: self.clock.now(),
+				in_flight_htlc: in_flight.clone(),
+			}));
+		}
+
+		return Ok(in_flight);
+	}
+}
+
+impl<C: Deref + Clone> LocalResourceManager for ResourceManager<C>
+	where C::Target: Clock,
+{
+	fn forward_htlc(&mut self, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager> {
+		return self.forward_htlc_shared(proposed_htlc);
+	}
+
+	fn resolve_htlc(&mut self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrResourceManager> {
+		return self.resolve_htlc_shared(resolved_htlc);
+	}
+
+	fn assign_outgoing_index(&mut self, incoming_channel: ShortChannelId, incoming_index: u32, outgoing_index: u32) -> Result<(), ErrResourceManager> {
+		return self.assign_outgoing_index_shared(incoming_channel, incoming_index, outgoing_index);
+	}
+
+	fn cancel_forward(&mut self, incoming_channel: ShortChannelId, incoming_index: u32) -> Result<InFlightHTLC, ErrResourceManager> {
+		return self.cancel_forward_shared(incoming_channel, incoming_index);
+	}
+}
+
+/// Returns the class of bucket that a reconciled HTLC should occupy, based on the outcome it was forwarded with.
+fn reconciled_class(in_flight_htlc: &InFlightHTLC) -> Option<HTLCClass> {
+	return match in_flight_htlc.outgoing_decision {
+		ForwardOutcome::ForwardOutcomeNoResources => None,
+		ForwardOutcome::ForwardOutcomeEndorsed => Some(HTLCClass::EndorsedSufficientReputation),
+		ForwardOutcome::ForwardOutcomeUnendorsed => match in_flight_htlc.proposed_htlc.incoming_endorsed {
+			Endorsement::EndorsementTrue => Some(HTLCClass::EndorsedInsufficientReputation),
+			_ => Some(HTLCClass::Unendorsed),
+		},
+	};
+}
+
+/// Returns an error if the HTLC's amount is above the maximum amount that can be forwarded, or if it would
+/// pay a negative fee.
+fn check_amount(proposed_htlc: &ProposedHTLC) -> Result<(), ErrResourceManager> {
+	if proposed_htlc.outgoing_amount_msat > MAX_MILLI_SATOSHI {
+		return Err(ErrResourceManager::AmountAboveMaximum {
+			incoming_channel: proposed_htlc.incoming_channel,
+			incoming_index: proposed_htlc.incoming_index,
+			amount_msat: proposed_htlc.outgoing_amount_msat,
+		});
+	}
+
+	if proposed_htlc.forwarding_fee().is_none() {
+		return Err(ErrResourceManager::NegativeFee {
+			incoming_channel: proposed_htlc.incoming_channel,
+			incoming_index: proposed_htlc.incoming_index,
+			incoming_amount_msat: proposed_htlc.incoming_amount_msat,
+			outgoing_amount_msat: proposed_htlc.outgoing_amount_msat,
+		});
+	}
+
+	return Ok(());
+}
+
+/// Locks the tracker of the channel provided.
+fn lock<T>(short_channel_id: ShortChannelId, tracker: &Mutex<T>) -> Result<MutexGuard<'_, T>, ErrResourceManager> {
+	return tracker.lock().map_err(|_| ErrResourceManager::ChannelLockPoisoned(short_channel_id));
+}
+
+/// Locks the reputation tracker of the peer provided.
+fn lock_peer<T>(node_id: NodeId, tracker: &Mutex<T>) -> Result<MutexGuard<'_, T>, ErrResourceManager> {
+	return tracker.lock().map_err(|_| ErrResourceManager::PeerLockPoisoned(node_id));
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 	use super::*;
 
+	use std::time::SystemTime;
+
+	use crate::resources::audit::RingBufferSink;
+	use crate::resources::clock::MockClock;
+	use crate::resources::decaying_average::ErrDecayingAverage;
+	use crate::resources::interface::{Bucket, ChannelDirection, Endorsement, ForwardOutcome};
+	use crate::resources::persistence::{Readable, Writeable};
+	use crate::resources::reputation_tracker::outstanding_risk;
+
+	fn proposed_htlc(incoming_channel: u64, outgoing_channel: u64, incoming_index: u32) -> ProposedHTLC {
+		return ProposedHTLC {
+			incoming_channel: ShortChannelId(incoming_channel),
+			outgoing_channel: ShortChannelId(outgoing_channel),
+			incoming_index,
+			incoming_endorsed: Endorsement::EndorsementFalse,
+			incoming_amount_msat: MilliSatoshi(10_100),
+			outgoing_amount_msat: MilliSatoshi(10_000),
+			cltv_expiry_delta: 40,
+			cltv_expiry: 40,
+		};
+	}
+
 	#[test]
 	fn test_resource_manager() {
 		let manager_config = ManagerConfig::default();
-		manager_config.validate();
-		manager_config.reputation_window();
+		assert_eq!(manager_config.validate(), true);
+		assert_eq!(manager_config.reputation_window(), Duration::from_secs(60 * 60 * 24));
+
+		let invalid_config = ManagerConfig { protected_percentage: 101, ..ManagerConfig::default() };
+		assert_eq!(ResourceManager::new(invalid_config, Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).is_err(), true);
+
+		let invalid_config = ManagerConfig { revenue_window: Duration::from_secs(0), ..ManagerConfig::default() };
+		assert_eq!(ResourceManager::new(invalid_config, Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).is_err(), true);
+	}
+
+	#[test]
+	fn test_resource_manager_channel_registry() {
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+
+		let incoming_info = ChannelInfo { direction: ChannelDirection::Incoming, ..ChannelInfo::new(100, MilliSatoshi(100_000)) };
+		let outgoing_info = ChannelInfo { direction: ChannelDirection::Outgoing, ..ChannelInfo::new(100, MilliSatoshi(100_000)) };
+
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), incoming_info), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), outgoing_info), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(3), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(3), ChannelInfo::new(100, MilliSatoshi(100_000))), Err(ErrResourceManager::ChannelExists(ShortChannelId(3))));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(4), ChannelInfo::new(500, MilliSatoshi(100_000))).is_err(), true);
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 3, 1)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 2, 0)).is_ok(), true);
+
+		// Channels can only be used in the roles that they have been registered for.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(2, 3, 0)).is_err(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 1, 1)).is_err(), true);
+
+		assert_eq!(resource_manager.update_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 1, 1)).is_ok(), true);
+
+		// Changing an outgoing channel's limits resizes its buckets, keeping the HTLC that is in flight.
+		assert_eq!(resource_manager.update_channel(ShortChannelId(2), ChannelInfo { direction: ChannelDirection::Outgoing, ..ChannelInfo::new(2, MilliSatoshi(100_000)) }), Ok(()));
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 3)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+		assert_eq!(resource_manager.update_channel(ShortChannelId(2), ChannelInfo { direction: ChannelDirection::Outgoing, ..ChannelInfo::new(500, MilliSatoshi(100_000)) }).is_err(), true);
+		assert_eq!(resource_manager.channels.get(&ShortChannelId(2)).map(|info| info.in_flight_htlc_limit), Some(2));
+
+		assert_eq!(resource_manager.remove_channel(ShortChannelId(2)), Ok(()));
+		assert_eq!(resource_manager.remove_channel(ShortChannelId(2)), Err(ErrResourceManager::ChannelNotFound(ShortChannelId(2))));
+		assert_eq!(resource_manager.update_channel(ShortChannelId(2), ChannelInfo::new(100, MilliSatoshi(100_000))), Err(ErrResourceManager::ChannelNotFound(ShortChannelId(2))));
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 2)).is_err(), true);
+	}
+
+	#[test]
+	fn test_resource_manager_resolve_htlc() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+
+		let resolved_htlc = |incoming_index: u32, timestamp_settled: SystemTime| ResolvedHTLC {
+			timestamp_settled,
+			incoming_index,
+			incoming_channel: ShortChannelId(1),
+			outgoing_index: incoming_index,
+			outgoing_channel: ShortChannelId(2),
+			success: true,
+		};
+
+		// A successful HTLC that resolves within the resolution period is credited with its fee.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		mock_clock.advance(Duration::from_secs(30));
+		assert_eq!(resource_manager.resolve_htlc(resolved_htlc(0, mock_clock.now())).is_ok(), true);
+
+		let reputation = resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().incoming_reputation(0);
+		assert_eq!(reputation.incoming_revenue, 100.0);
+		assert_eq!(reputation.in_flight_risk, 0.0);
+
+		// Unknown HTLCs can't be resolved.
+		assert_eq!(resource_manager.resolve_htlc(resolved_htlc(0, mock_clock.now())).err(), Some(ErrResourceManager::Reputation {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 0,
+			err: ErrReputation::ResolutionNotFound,
+		}));
+
+		// HTLCs must be resolved on the channel that they were forwarded over.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 1)).is_ok(), true);
+		let mismatched_htlc = ResolvedHTLC { outgoing_channel: ShortChannelId(1), ..resolved_htlc(1, mock_clock.now()) };
+		assert_eq!(resource_manager.resolve_htlc(mismatched_htlc).err(), Some(ErrResourceManager::OutgoingChannelMismatch {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 1,
+			expected: ShortChannelId(2),
+			actual: ShortChannelId(1),
+		}));
+		assert_eq!(resource_manager.resolve_htlc(resolved_htlc(1, mock_clock.now())).is_ok(), true);
+
+		let oversized_htlc = ProposedHTLC { outgoing_amount_msat: MilliSatoshi(MAX_MILLI_SATOSHI.to_msat() + 1), ..proposed_htlc(1, 2, 2) };
+		assert_eq!(resource_manager.forward_htlc(oversized_htlc).err(), Some(ErrResourceManager::AmountAboveMaximum {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 2,
+			amount_msat: MilliSatoshi(MAX_MILLI_SATOSHI.to_msat() + 1),
+		}));
+
+		// HTLCs that would cost us to forward are rejected rather than wrapping their fee.
+		let negative_fee_htlc = ProposedHTLC { outgoing_amount_msat: MilliSatoshi(10_200), ..proposed_htlc(1, 2, 2) };
+		assert_eq!(resource_manager.forward_htlc(negative_fee_htlc).err(), Some(ErrResourceManager::NegativeFee {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 2,
+			incoming_amount_msat: MilliSatoshi(10_100),
+			outgoing_amount_msat: MilliSatoshi(10_200),
+		}));
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(5, 2, 2)).err(), Some(ErrResourceManager::UnknownIncomingChannel(ShortChannelId(5))));
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 5, 2)).err(), Some(ErrResourceManager::UnknownOutgoingChannel(ShortChannelId(5))));
+
+	}
+
+	#[test]
+	fn test_resource_manager_resolve_clock_backwards() {
+		let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+		let mock_clock = Arc::new(MockClock::new(start_time));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		for short_channel_id in 1..=3 {
+			assert_eq!(resource_manager.add_channel(ShortChannelId(short_channel_id), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		}
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 2, 0)).is_ok(), true);
+
+		// Crediting the outgoing channel's revenue moves its last update forward.
+		mock_clock.advance(Duration::from_secs(60));
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(3), 0, ShortChannelId(2), 0, true)).is_ok(), true);
+
+		// When the clock steps backwards, the outgoing channel can't credit the HTLC's fees, but its resources are
+		// still released rather than being left occupied by a HTLC that is no longer tracked.
+		mock_clock.set(start_time + Duration::from_secs(30));
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 0, true)).err(),
+			Some(ErrResourceManager::DecayingAverage { short_channel_id: ShortChannelId(2), err: ErrDecayingAverage::TimeAdditionError }));
+		let occupancy = resource_manager.target_channels.get(&ShortChannelId(2)).unwrap().bucket_occupancy();
+		assert_eq!(occupancy[1].in_flight_slots, 0);
+		assert_eq!(occupancy[1].in_flight_liquidity_msat, MilliSatoshi::ZERO);
+		assert_eq!(resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().in_flight_htlcs().len(), 0);
+	}
+
+	#[test]
+	fn test_resource_manager_block_height() {
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+
+		// HTLCs expire at the absolute height of the incoming HTLC, rather than an assumed delta from the
+		// current height.
+		assert_eq!(resource_manager.update_block_height(800_000), Ok(()));
+		let endorsed_htlc = ProposedHTLC { incoming_endorsed: Endorsement::EndorsementTrue, cltv_expiry: 800_100, ..proposed_htlc(1, 2, 0) };
+		assert_eq!(resource_manager.forward_htlc(endorsed_htlc).is_ok(), true);
+		let channel_reputation_tracker = resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap();
+		assert_eq!(channel_reputation_tracker.in_flight_htlcs()[0].expiry_height, Some(800_100));
+		let in_flight_risk = channel_reputation_tracker.incoming_reputation(800_000).in_flight_risk;
+		assert_eq!(in_flight_risk, outstanding_risk(600.0, 100, 100, Duration::from_secs(90)));
+
+		// Heights that go backwards are ignored, so they can't overstate the risk of HTLCs in flight.
+		assert_eq!(resource_manager.update_block_height(799_000), Ok(()));
+		assert_eq!(resource_manager.block_height, 800_000);
+		assert_eq!(resource_manager.update_block_height(800_050), Ok(()));
+		assert_eq!(resource_manager.block_height, 800_050);
+	}
+
+	#[test]
+	fn test_resource_manager_restore() {
+		let start_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+		let mock_clock = Arc::new(MockClock::new(start_time));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+
+		resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(10, MilliSatoshi(100_000))).unwrap();
+		resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(10, MilliSatoshi(100_000))).unwrap();
+
+		// Build up some reputation for the incoming channel and leave a HTLC in flight.
+		let endorsed_htlc = |incoming_index: u32| ProposedHTLC {
+			incoming_endorsed: Endorsement::EndorsementTrue,
+			incoming_amount_msat: MilliSatoshi(11_000),
+			..proposed_htlc(1, 2, incoming_index)
+		};
+		resource_manager.forward_htlc(endorsed_htlc(0)).unwrap();
+		resource_manager.forward_htlc(endorsed_htlc(1)).unwrap();
+		mock_clock.advance(Duration::from_secs(10));
+		resource_manager.resolve_htlc(ResolvedHTLC {
+			timestamp_settled: mock_clock.now(),
+			incoming_index: 0,
+			incoming_channel: ShortChannelId(1),
+			outgoing_index: 0,
+			outgoing_channel: ShortChannelId(2),
+			success: true,
+		}).unwrap();
+
+		let path = std::env::temp_dir().join(format!("rust-lrc-snapshot-{}", std::process::id()));
+		resource_manager.snapshot().unwrap().write_to_file(&path).unwrap();
+		let snapshot = ResourceManagerSnapshot::read_from_file(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let incoming = snapshot.channels.iter().find(|channel| channel.short_channel_id == ShortChannelId(1)).unwrap();
+		let reputation = incoming.reputation.as_ref().unwrap();
+		assert_eq!(reputation.revenue, DecayingAverageStart { last_update: mock_clock.now(), value: 1_000.0 });
+		assert_eq!(reputation.in_flight_htlcs.len(), 1);
+
+		let outgoing = snapshot.channels.iter().find(|channel| channel.short_channel_id == ShortChannelId(2)).unwrap();
+		assert_eq!(outgoing.target_revenue.as_ref().unwrap().value, 1_000.0);
+
+		// Restart after some downtime, which should be reflected in the decayed values.
+		mock_clock.advance(Duration::from_secs(60));
+		let mut restored_manager = ResourceManager::from_snapshot(snapshot, mock_clock.clone()).unwrap();
+		let restored_reputation = restored_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().incoming_reputation(0);
+		assert_eq!(restored_reputation.incoming_revenue < 1_000.0, true);
+		assert_eq!(restored_reputation.in_flight_risk > 0.0, true);
+
+		// The HTLC that was in flight can still be resolved against the restored state.
+		let in_flight_htlc = restored_manager.resolve_htlc(ResolvedHTLC {
+			timestamp_settled: mock_clock.now(),
+			incoming_index: 1,
+			incoming_channel: ShortChannelId(1),
+			outgoing_index: 1,
+			outgoing_channel: ShortChannelId(2),
+			success: true,
+		});
+		assert_eq!(in_flight_htlc.is_ok(), true);
+
+		// HTLCs that are in flight over the limits of a channel that was shrunk can still be restored.
+		for incoming_index in 2..6 {
+			assert_eq!(restored_manager.forward_htlc(proposed_htlc(1, 2, incoming_index)).unwrap().bucket(), Some(Bucket::GENERAL));
+		}
+		assert_eq!(restored_manager.update_channel(ShortChannelId(2), ChannelInfo::new(2, MilliSatoshi(100_000))), Ok(()));
+
+		let restored_manager = ResourceManager::from_snapshot(restored_manager.snapshot().unwrap(), mock_clock.clone()).unwrap();
+		let occupancy = restored_manager.target_channels.get(&ShortChannelId(2)).unwrap().bucket_occupancy();
+		assert_eq!(occupancy[1].in_flight_slots, 4);
+		assert_eq!(occupancy[1].slot_limit, 1);
+		assert_eq!(restored_manager.evaluate_htlc(&proposed_htlc(1, 2, 6)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+	}
+
+	#[test]
+	fn test_resource_manager_audit() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		let audit_sink = Arc::new(RingBufferSink::new(10));
+		resource_manager.set_audit_sink(audit_sink.clone());
+
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		mock_clock.advance(Duration::from_secs(30));
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 0, true)).is_ok(), true);
+
+		let events = audit_sink.events();
+		assert_eq!(events.len(), 2);
+		match &events[0] {
+			AuditEvent::Forward(forward) => {
+				assert_eq!(forward.decision.forward_outcome, ForwardOutcome::ForwardOutcomeUnendorsed);
+				assert_eq!(forward.decision.bucket, Some(Bucket::GENERAL));
+				assert_eq!(forward.bucket_occupancy[1].in_flight_slots, 1);
+				assert_eq!(forward.bucket_occupancy[1].in_flight_liquidity_msat, MilliSatoshi(10_000));
+			},
+			_ => panic!("expected forward event"),
+		}
+		match &events[1] {
+			AuditEvent::Resolve(resolve) => assert_eq!(resolve.effective_fees, 100.0),
+			_ => panic!("expected resolve event"),
+		}
+	}
+
+	#[test]
+	fn test_resource_manager_decay_windows() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let manager_config = ManagerConfig::default();
+		let mut resource_manager = ResourceManager::new(manager_config, mock_clock.clone()).unwrap();
+
+		// Channel 3 halves its revenue and reputation over a shorter period than the manager's windows.
+		let override_window = Duration::from_secs(60);
+		let override_info = ChannelInfo {
+			revenue_window: Some(override_window),
+			reputation_window: Some(override_window),
+			..ChannelInfo::new(100, MilliSatoshi(100_000))
+		};
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(3), override_info), Ok(()));
+
+		let zero_window = ChannelInfo { revenue_window: Some(Duration::from_secs(0)), ..ChannelInfo::new(100, MilliSatoshi(100_000)) };
+		assert_eq!(resource_manager.add_channel(ShortChannelId(4), zero_window), Err(ErrResourceManager::InvalidChannelInfo(ShortChannelId(4))));
+
+		// Each HTLC earns a fee of 100 msat for the incoming channel's reputation and the outgoing channel's revenue.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 3, 0)).is_ok(), true);
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 0, true)).is_ok(), true);
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(3), 0, ShortChannelId(3), 0, true)).is_ok(), true);
+
+		let mut assert_values = |short_channel_id: u64, reputation: f64, revenue: f64| {
+			let incoming_revenue = resource_manager.channel_reputation.get_mut(&ShortChannelId(short_channel_id)).unwrap().get_mut().unwrap()
+				.incoming_reputation(0).incoming_revenue;
+			assert!((incoming_revenue - reputation).abs() < 1e-6, "reputation {} != {}", incoming_revenue, reputation);
+
+			let outgoing_revenue = resource_manager.target_channels.get(&ShortChannelId(short_channel_id)).unwrap()
+				.revenue_value();
+			assert!((outgoing_revenue - revenue).abs() < 1e-6, "revenue {} != {}", outgoing_revenue, revenue);
+		};
+
+		// Values halve over half of their window.
+		mock_clock.advance(override_window / 2);
+		assert_values(3, 50.0, 50.0);
+
+		mock_clock.set(SystemTime::UNIX_EPOCH + manager_config.revenue_window / 2);
+		assert_values(2, 0.0, 50.0);
+
+		mock_clock.set(SystemTime::UNIX_EPOCH + manager_config.reputation_window() / 2);
+		assert_values(1, 50.0, 0.0);
+	}
+
+	#[test]
+	fn test_resource_manager_evaluate_htlc() {
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(2, MilliSatoshi(100_000))), Ok(()));
+
+		// Evaluating a HTLC does not use up the single general slot of the outgoing channel.
+		for _ in 0..2 {
+			let decision = resource_manager.evaluate_htlc(&proposed_htlc(1, 2, 0)).unwrap();
+			assert_eq!(decision.forward_outcome(), ForwardOutcome::ForwardOutcomeUnendorsed);
+			assert_eq!(decision.bucket(), Some(Bucket::GENERAL));
+		}
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeUnendorsed);
+		assert_eq!(resource_manager.evaluate_htlc(&proposed_htlc(1, 2, 1)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+		assert_eq!(resource_manager.evaluate_htlc(&proposed_htlc(1, 3, 1)).err(), Some(ErrResourceManager::UnknownOutgoingChannel(ShortChannelId(3))));
+
+		let snapshot = resource_manager.snapshot().unwrap();
+		let incoming = snapshot.channels.iter().find(|channel| channel.short_channel_id == ShortChannelId(1)).unwrap();
+		assert_eq!(incoming.reputation.as_ref().unwrap().in_flight_htlcs.len(), 1);
+	}
+
+	#[test]
+	fn test_resource_manager_endorsement_policy() {
+		// An endorsed HTLC from a channel without reputation is forwarded in the general bucket.
+		let policies = [
+			(EndorsementPolicy::RelayAsReceived, Endorsement::EndorsementTrue),
+			(EndorsementPolicy::EndorseIfProtected, Endorsement::EndorsementFalse),
+			(EndorsementPolicy::NeverEndorse, Endorsement::EndorsementFalse),
+		];
+		for (endorsement_policy, expected) in policies {
+			let manager_config = ManagerConfig { endorsement_policy, ..ManagerConfig::default() };
+			let mut resource_manager = ResourceManager::new(manager_config, Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+			assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+			assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(2, MilliSatoshi(100_000))), Ok(()));
+
+			let mut htlc = proposed_htlc(1, 2, 0);
+			htlc.incoming_endorsed = Endorsement::EndorsementTrue;
+			let decision = resource_manager.forward_htlc(htlc.clone()).unwrap();
+			assert_eq!(decision.forward_outcome(), ForwardOutcome::ForwardOutcomeUnendorsed);
+			assert_eq!(decision.outgoing_endorsement(), expected);
+
+			// HTLCs that are not forwarded have no outgoing signal.
+			htlc.incoming_index = 1;
+			let decision = resource_manager.forward_htlc(htlc).unwrap();
+			assert_eq!(decision.forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+			assert_eq!(decision.outgoing_endorsement(), Endorsement::EndorsementNone);
+		}
+
+		assert_eq!(EndorsementPolicy::EndorseIfProtected.outgoing_endorsement(&Endorsement::EndorsementTrue,
+			&ForwardOutcome::ForwardOutcomeEndorsed), Endorsement::EndorsementTrue);
+		assert_eq!(EndorsementPolicy::NeverEndorse.outgoing_endorsement(&Endorsement::EndorsementTrue,
+			&ForwardOutcome::ForwardOutcomeEndorsed), Endorsement::EndorsementFalse);
+		assert_eq!(EndorsementPolicy::RelayAsReceived.outgoing_endorsement(&Endorsement::EndorsementNone,
+			&ForwardOutcome::ForwardOutcomeUnendorsed), Endorsement::EndorsementNone);
+	}
+
+	#[test]
+	fn test_resource_manager_atomic_bucketer() {
+		let manager_config = ManagerConfig { bucketer: BucketerType::Atomic, ..ManagerConfig::default() };
+		let fair_share = BucketConfig::protected(50).with_general_fair_share(50);
+		assert_eq!(ResourceManager::with_bucket_config(manager_config, fair_share, Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).err(),
+			Some(ErrResourceManager::InvalidConfig));
+
+		let mut resource_manager = ResourceManager::new(manager_config, Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(2, MilliSatoshi(100_000))), Ok(()));
+
+		// The outgoing channel's single general slot is reserved and released by the atomic bucketer.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).unwrap().bucket(), Some(Bucket::GENERAL));
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 1)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(SystemTime::UNIX_EPOCH, ShortChannelId(1), 0, ShortChannelId(2), 0, true)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 2)).unwrap().bucket(), Some(Bucket::GENERAL));
+
+		// The bucketer is kept across restarts, and reoccupied by the HTLC in flight.
+		let restored_manager = ResourceManager::from_snapshot(resource_manager.snapshot().unwrap(), Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+		assert_eq!(restored_manager.manager_configuration.bucketer, BucketerType::Atomic);
+		assert_eq!(restored_manager.evaluate_htlc(&proposed_htlc(1, 2, 3)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+	}
+
+	#[test]
+	fn test_resource_manager_cancel_forward() {
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), Arc::new(MockClock::new(SystemTime::UNIX_EPOCH))).unwrap();
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(2, MilliSatoshi(100_000))), Ok(()));
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeUnendorsed);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 1)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeNoResources);
+
+		// Cancelling a forward releases its slot without affecting reputation or revenue.
+		assert_eq!(resource_manager.cancel_forward(ShortChannelId(1), 0).unwrap().bucket, Some(Bucket::GENERAL));
+		assert_eq!(resource_manager.cancel_forward(ShortChannelId(1), 1).unwrap().bucket, None);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 2)).unwrap().forward_outcome(), ForwardOutcome::ForwardOutcomeUnendorsed);
+
+		let reputation = resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().incoming_reputation(0);
+		assert_eq!(reputation.incoming_revenue, 0.0);
+		assert_eq!(resource_manager.target_channels.get(&ShortChannelId(2)).unwrap().revenue_value(), 0.0);
+
+		assert_eq!(resource_manager.cancel_forward(ShortChannelId(1), 0).err(), Some(ErrResourceManager::Reputation {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 0,
+			err: ErrReputation::ResolutionNotFound,
+		}));
+		assert_eq!(resource_manager.cancel_forward(ShortChannelId(5), 0).err(), Some(ErrResourceManager::UnknownIncomingChannel(ShortChannelId(5))));
+	}
+
+	#[test]
+	fn test_resource_manager_circuits() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		for short_channel_id in 1..=3 {
+			assert_eq!(resource_manager.add_channel(ShortChannelId(short_channel_id), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		}
+
+		// HTLCs are identified by their incoming channel and index, so a duplicate add is rejected without
+		// reserving any resources.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).err(), Some(ErrResourceManager::Reputation {
+			incoming_channel: ShortChannelId(1),
+			incoming_index: 0,
+			err: ErrReputation::DuplicateHTLC,
+		}));
+		let occupancy = resource_manager.target_channels.get(&ShortChannelId(2)).unwrap().bucket_occupancy();
+		assert_eq!(occupancy[1].in_flight_slots, 2);
+
+		// Once assigned, the outgoing index can't change and must match the resolution.
+		assert_eq!(resource_manager.assign_outgoing_index(ShortChannelId(1), 0, 5), Ok(()));
+		assert_eq!(resource_manager.assign_outgoing_index(ShortChannelId(1), 0, 5), Ok(()));
+		assert_eq!(resource_manager.assign_outgoing_index(ShortChannelId(1), 0, 6).is_err(), true);
+
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 4, true)).err(),
+			Some(ErrResourceManager::OutgoingIndexMismatch { incoming_channel: ShortChannelId(1), incoming_index: 0, expected: 5, actual: 4 }));
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 5, true)).unwrap().outgoing_circuit(),
+			Some(CircuitKey { short_channel_id: ShortChannelId(2), htlc_index: 5 }));
+
+		// HTLCs without an assigned outgoing index, such as those that fail locally, can still be resolved.
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(3), 0, ShortChannelId(2), 0, false)).is_ok(), true);
+	}
+
+	#[test]
+	fn test_resource_manager_reconcile() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 1)).is_ok(), true);
+
+		let live_htlc = |incoming_index: u32, outcome: ForwardOutcome| InFlightHTLC::new(SystemTime::UNIX_EPOCH, outcome, 40,
+			None, proposed_htlc(1, 2, incoming_index));
+
+		// Invalid HTLCs are rejected before any state is changed.
+		let unknown_channel = InFlightHTLC::new(SystemTime::UNIX_EPOCH, ForwardOutcome::ForwardOutcomeUnendorsed, 40, None, proposed_htlc(1, 5, 4));
+		assert_eq!(resource_manager.reconcile(vec![live_htlc(1, ForwardOutcome::ForwardOutcomeUnendorsed), unknown_channel].into_iter()).err(),
+			Some(ErrResourceManager::UnknownOutgoingChannel(ShortChannelId(5))));
+
+		// The node still has HTLC 1, which the manager tracks, and HTLCs 2 and 3 which it lost track of.
+		let report = resource_manager.reconcile(vec![
+			live_htlc(1, ForwardOutcome::ForwardOutcomeUnendorsed),
+			live_htlc(2, ForwardOutcome::ForwardOutcomeUnendorsed),
+			live_htlc(3, ForwardOutcome::ForwardOutcomeNoResources),
+		].into_iter()).unwrap();
+
+		assert_eq!(report.restored, vec![CircuitKey { short_channel_id: ShortChannelId(1), htlc_index: 2 }, CircuitKey { short_channel_id: ShortChannelId(1), htlc_index: 3 }]);
+		assert_eq!(report.unallocated.len(), 0);
+		assert_eq!(report.removed.len(), 1);
+		assert_eq!(report.removed[0].incoming_circuit(), CircuitKey { short_channel_id: ShortChannelId(1), htlc_index: 0 });
+
+		let occupancy = resource_manager.target_channels.get(&ShortChannelId(2)).unwrap().bucket_occupancy();
+		assert_eq!(occupancy[1].in_flight_slots, 2);
+		assert_eq!(occupancy[1].in_flight_liquidity_msat, MilliSatoshi(20_000));
+
+		// Reconciled HTLCs are resolved like any other, and removed HTLCs are no longer tracked.
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 2, ShortChannelId(2), 0, true)).unwrap().bucket, Some(Bucket::GENERAL));
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 3, ShortChannelId(2), 0, false)).unwrap().bucket, None);
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 0, true)).is_err(), true);
+	}
+
+	#[test]
+	fn test_resource_manager_close_channel() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		for short_channel_id in 1..=3 {
+			assert_eq!(resource_manager.add_channel(ShortChannelId(short_channel_id), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		}
+
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(2, 3, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(3, 1, 0)).is_ok(), true);
+		let endorsed_htlc = ProposedHTLC { incoming_endorsed: Endorsement::EndorsementTrue, ..proposed_htlc(1, 3, 1) };
+		assert_eq!(resource_manager.forward_htlc(endorsed_htlc).is_ok(), true);
+
+		// HTLCs in flight to and from the closed channel release their resources without affecting reputation.
+		let resolved = resource_manager.close_channel(ShortChannelId(2), CloseTreatment::Release).unwrap();
+		assert_eq!(resolved.len(), 2);
+		let occupancy = resource_manager.target_channels.get(&ShortChannelId(3)).unwrap().bucket_occupancy();
+		assert_eq!(occupancy[1].in_flight_slots, 1);
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(2), 0, true)).is_err(), true);
+		assert_eq!(resource_manager.close_channel(ShortChannelId(2), CloseTreatment::Release).err(), Some(ErrResourceManager::ChannelNotFound(ShortChannelId(2))));
+
+		let archive = resource_manager.archived_channel(ShortChannelId(2)).unwrap();
+		assert_eq!(archive.reputation, Some(0.0));
+		assert_eq!(archive.revenue, Some(0.0));
+
+		// Failing HTLCs on close charges the incoming channel for an endorsed HTLC held beyond the resolution period.
+		mock_clock.advance(Duration::from_secs(180));
+		assert_eq!(resource_manager.close_channel(ShortChannelId(3), CloseTreatment::Fail).unwrap().len(), 2);
+		let reputation = resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().incoming_reputation(0);
+		assert_eq!(reputation.incoming_revenue, -100.0);
+		assert_eq!(reputation.in_flight_risk, 0.0);
+
+		// Archived channels are persisted with the rest of the manager's state.
+		let restored = ResourceManager::from_snapshot(resource_manager.snapshot().unwrap(), mock_clock.clone()).unwrap();
+		assert_eq!(restored.archived_channel(ShortChannelId(2)).is_some(), true);
+		assert_eq!(restored.archived_channel(ShortChannelId(3)).is_some(), true);
+		assert_eq!(restored.archived_channel(ShortChannelId(1)).is_none(), true);
+	}
+
+	#[test]
+	fn test_resource_manager_remove_channel_inflight() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		for short_channel_id in 1..=3 {
+			assert_eq!(resource_manager.add_channel(ShortChannelId(short_channel_id), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		}
+
+		let endorsed_htlc = |incoming_channel: u64, outgoing_channel: u64, incoming_index: u32| ProposedHTLC {
+			incoming_endorsed: Endorsement::EndorsementTrue,
+			..proposed_htlc(incoming_channel, outgoing_channel, incoming_index)
+		};
+		assert_eq!(resource_manager.forward_htlc(endorsed_htlc(2, 3, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(endorsed_htlc(1, 3, 0)).is_ok(), true);
+
+		// Removing the incoming side of a HTLC releases its resources on the outgoing channel.
+		assert_eq!(resource_manager.remove_channel(ShortChannelId(2)), Ok(()));
+		let occupancy = resource_manager.target_channels.get(&ShortChannelId(3)).unwrap().bucket_occupancy();
+		assert_eq!(occupancy[1].in_flight_slots, 1);
+		assert_eq!(occupancy[1].in_flight_liquidity_msat, MilliSatoshi(10_000));
+
+		// Removing the outgoing side of a HTLC stops it counting towards the incoming channel's risk.
+		assert_eq!(resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().incoming_reputation(0).in_flight_risk > 0.0, true);
+		assert_eq!(resource_manager.remove_channel(ShortChannelId(3)), Ok(()));
+		let channel_reputation_tracker = resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap();
+		assert_eq!(channel_reputation_tracker.in_flight_htlcs().len(), 0);
+		assert_eq!(channel_reputation_tracker.incoming_reputation(0).in_flight_risk, 0.0);
+	}
+
+	#[test]
+	fn test_resource_manager_peer_reputation() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let manager_config = ManagerConfig { aggregate_peer_reputation: true, ..ManagerConfig::default() };
+		let mut resource_manager = ResourceManager::new(manager_config, mock_clock.clone()).unwrap();
+
+		let peer_info = ChannelInfo { peer: Some(NodeId([2; 33])), ..ChannelInfo::new(100, MilliSatoshi(100_000)) };
+		assert_eq!(resource_manager.add_channel(ShortChannelId(1), peer_info), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(2), peer_info), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(3), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		assert_eq!(resource_manager.channel_reputation.len(), 1);
+
+		// Fees earned on one of the peer's channels count towards the reputation of all of them.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 3, 0)).is_ok(), true);
+		assert_eq!(resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(3), 0, true)).is_ok(), true);
+		assert_eq!(resource_manager.lock_reputation(ShortChannelId(2)).unwrap().incoming_reputation(0).incoming_revenue, 100.0);
+
+		let endorsed_htlc = ProposedHTLC { incoming_endorsed: Endorsement::EndorsementTrue, ..proposed_htlc(2, 3, 0) };
+		assert_eq!(resource_manager.forward_htlc(endorsed_htlc).is_ok(), true);
+		let peer_reputation = resource_manager.lock_reputation(ShortChannelId(1)).unwrap().incoming_reputation(0);
+		assert_eq!(peer_reputation.in_flight_risk > 0.0, true);
+
+		// A new channel with the peer inherits its reputation once the old channel is removed.
+		assert_eq!(resource_manager.remove_channel(ShortChannelId(1)), Ok(()));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(4), peer_info), Ok(()));
+		assert_eq!(resource_manager.lock_reputation(ShortChannelId(4)).unwrap().incoming_reputation(0).in_flight_risk, peer_reputation.in_flight_risk);
+
+		// Peer reputation is persisted with the in-flight HTLCs of all of the peer's channels.
+		let mut encoded = Vec::new();
+		resource_manager.snapshot().unwrap().write(&mut encoded).unwrap();
+		let snapshot = ResourceManagerSnapshot::read(&mut &encoded[..]).unwrap();
+		let mut restored = ResourceManager::from_snapshot(snapshot, mock_clock.clone()).unwrap();
+		let restored_reputation = restored.lock_reputation(ShortChannelId(4)).unwrap().incoming_reputation(0);
+		assert_eq!(restored_reputation.incoming_revenue, 100.0);
+		assert_eq!(restored_reputation.in_flight_risk, peer_reputation.in_flight_risk);
+		assert_eq!(restored.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(2), 0, ShortChannelId(3), 0, true)).is_ok(), true);
+	}
+
+	#[test]
+	fn test_resource_manager_channel_aliases() {
+		let mock_clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH));
+		let mut resource_manager = ResourceManager::new(ManagerConfig::default(), mock_clock.clone()).unwrap();
+		for short_channel_id in 1..=3 {
+			assert_eq!(resource_manager.add_channel(ShortChannelId(short_channel_id), ChannelInfo::new(100, MilliSatoshi(100_000))), Ok(()));
+		}
+
+		assert_eq!(resource_manager.announce_short_channel_id(ShortChannelId(1), ShortChannelId(100)), Ok(()));
+		assert_eq!(resource_manager.announce_short_channel_id(ShortChannelId(100), ShortChannelId(100)), Ok(()));
+		assert_eq!(resource_manager.announce_short_channel_id(ShortChannelId(1), ShortChannelId(3)), Err(ErrResourceManager::ChannelExists(ShortChannelId(3))));
+		assert_eq!(resource_manager.announce_short_channel_id(ShortChannelId(2), ShortChannelId(100)), Err(ErrResourceManager::ChannelExists(ShortChannelId(100))));
+		assert_eq!(resource_manager.announce_short_channel_id(ShortChannelId(4), ShortChannelId(400)), Err(ErrResourceManager::ChannelNotFound(ShortChannelId(4))));
+		assert_eq!(resource_manager.add_channel(ShortChannelId(100), ChannelInfo::new(100, MilliSatoshi(100_000))), Err(ErrResourceManager::ChannelExists(ShortChannelId(100))));
+
+		// A HTLC forwarded over an alias is tracked under the channel's id, and may be resolved after the outgoing
+		// channel has been spliced.
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(100, 2, 0)).is_ok(), true);
+		assert_eq!(resource_manager.forward_htlc(proposed_htlc(1, 2, 0)).is_err(), true);
+		assert_eq!(resource_manager.announce_short_channel_id(ShortChannelId(2), ShortChannelId(200)), Ok(()));
+		assert_eq!(resource_manager.channel_id(ShortChannelId(200)), Some(ShortChannelId(2)));
+
+		let in_flight = resource_manager.resolve_htlc(ResolvedHTLC::new(mock_clock.now(), ShortChannelId(1), 0, ShortChannelId(200), 0, true)).unwrap();
+		assert_eq!(in_flight.proposed_htlc.incoming_channel, ShortChannelId(1));
+		assert_eq!(resource_manager.channel_reputation.get_mut(&ShortChannelId(1)).unwrap().get_mut().unwrap().incoming_reputation(0).incoming_revenue, 100.0);
+
+		// Aliases are persisted, and removed with their channel.
+		let restored = ResourceManager::from_snapshot(resource_manager.snapshot().unwrap(), mock_clock.clone()).unwrap();
+		assert_eq!(restored.channel_id(ShortChannelId(100)), Some(ShortChannelId(1)));
+
+		assert_eq!(resource_manager.remove_channel(ShortChannelId(100)), Ok(()));
+		assert_eq!(resource_manager.channel_id(ShortChannelId(1)), None);
+		assert_eq!(resource_manager.channel_id(ShortChannelId(100)), None);
+		assert_eq!(resource_manager.channel_aliases.len(), 1);
 	}
 }
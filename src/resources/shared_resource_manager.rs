@@ -0,0 +1,240 @@
+use std::ops::Deref;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::resources::audit::AuditSink;
+use crate::resources::clock::Clock;
+use crate::resources::error::ErrResourceManager;
+use crate::resources::interface::{ChannelInfo, ForwardDecision, InFlightHTLC, ProposedHTLC, ResolvedHTLC};
+use crate::resources::persistence::ResourceManagerSnapshot;
+use crate::resources::resource_manager::{CloseTreatment, ReconcileReport, ResourceManager};
+use crate::resources::types::ShortChannelId;
+
+/// This wraps a resource manager so that it can be shared between the threads that process HTLCs for
+/// different links.
+///
+/// Forwarding and resolving HTLCs only takes a shared lock on the manager, and then locks the reputation of
+/// the incoming channel, so HTLCs from different incoming channels do not contend. The outgoing channel's
+/// resources are reserved by its bucketer, which only takes a lock of its own if it is a
+/// [`crate::resources::resource_manager::BucketerType::Locking`] bucketer. Adding, removing or updating
+/// channels, or updating the block height, takes an exclusive lock.
+pub struct SharedResourceManager<C: Deref + Clone>
+	where C::Target: Clock,
+{
+	resource_manager: RwLock<ResourceManager<C>>,
+}
+
+impl<C: Deref + Clone> SharedResourceManager<C>
+	where C::Target: Clock,
+{
+	pub fn new(resource_manager: ResourceManager<C>) -> Self {
+		return SharedResourceManager {
+			resource_manager: RwLock::new(resource_manager),
+		};
+	}
+
+	/// This updates the manager to reflect that a proposed HTLC has been forwarded. See
+	/// [`crate::resources::interface::LocalResourceManager::forward_htlc`].
+	pub fn forward_htlc(&self, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager> {
+		return self.read()?.forward_htlc_shared(proposed_htlc);
+	}
+
+	/// This returns the decision that forwarding a HTLC would result in, without updating the manager. See
+	/// [`ResourceManager::evaluate_htlc`].
+	pub fn evaluate_htlc(&self, proposed_htlc: &ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager> {
+		return self.read()?.evaluate_htlc(proposed_htlc);
+	}
+
+	/// This updates the manager to reflect that an in-flight HTLC has been resolved. See
+	/// [`crate::resources::interface::LocalResourceManager::resolve_htlc`].
+	pub fn resolve_htlc(&self, resolved_htlc: ResolvedHTLC) -> Result<InFlightHTLC, ErrResourceManager> {
+		return self.read()?.resolve_htlc_shared(resolved_htlc);
+	}
+
+	/// This records the index that a forwarded HTLC was assigned on its outgoing channel. See
+	/// [`crate::resources::interface::LocalResourceManager::assign_outgoing_index`].
+	pub fn assign_outgoing_index(&self, incoming_channel: ShortChannelId, incoming_index: u32, outgoing_index: u32) -> Result<(), ErrResourceManager> {
+		return self.read()?.assign_outgoing_index_shared(incoming_channel, incoming_index, outgoing_index);
+	}
+
+	/// This releases the resources of a forward that could not be added to the outgoing channel. See
+	/// [`crate::resources::interface::LocalResourceManager::cancel_forward`].
+	pub fn cancel_forward(&self, incoming_channel: ShortChannelId, incoming_index: u32) -> Result<InFlightHTLC, ErrResourceManager> {
+		return self.read()?.cancel_forward_shared(incoming_channel, incoming_index);
+	}
+
+	pub fn add_channel(&self, short_channel_id: ShortChannelId, chan_info: ChannelInfo) -> Result<(), ErrResourceManager> {
+		return self.write()?.add_channel(short_channel_id, chan_info);
+	}
+
+	pub fn announce_short_channel_id(&self, short_channel_id: ShortChannelId, new_short_channel_id: ShortChannelId) -> Result<(), ErrResourceManager> {
+		return self.write()?.announce_short_channel_id(short_channel_id, new_short_channel_id);
+	}
+
+	pub fn remove_channel(&self, short_channel_id: ShortChannelId) -> Result<(), ErrResourceManager> {
+		return self.write()?.remove_channel(short_channel_id);
+	}
+
+	pub fn close_channel(&self, short_channel_id: ShortChannelId, treatment: CloseTreatment) -> Result<Vec<InFlightHTLC>, ErrResourceManager> {
+		return self.write()?.close_channel(short_channel_id, treatment);
+	}
+
+	pub fn update_channel(&self, short_channel_id: ShortChannelId, chan_info: ChannelInfo) -> Result<(), ErrResourceManager> {
+		return self.write()?.update_channel(short_channel_id, chan_info);
+	}
+
+	pub fn set_audit_sink(&self, audit_sink: Arc<dyn AuditSink + Send + Sync>) -> Result<(), ErrResourceManager> {
+		self.write()?.set_audit_sink(audit_sink);
+		return Ok(());
+	}
+
+	pub fn update_block_height(&self, block_height: u32) -> Result<(), ErrResourceManager> {
+		return self.write()?.update_block_height(block_height);
+	}
+
+	pub fn reconcile<I: Iterator<Item = InFlightHTLC>>(&self, in_flight: I) -> Result<ReconcileReport, ErrResourceManager> {
+		return self.write()?.reconcile(in_flight);
+	}
+
+	pub fn snapshot(&self) -> Result<ResourceManagerSnapshot, ErrResourceManager> {
+		return self.read()?.snapshot();
+	}
+
+	/// Returns the underlying resource manager.
+	pub fn into_inner(self) -> Result<ResourceManager<C>, ErrResourceManager> {
+		return self.resource_manager.into_inner().map_err(|_| ErrResourceManager::ManagerLockPoisoned);
+	}
+
+	fn read(&self) -> Result<RwLockReadGuard<'_, ResourceManager<C>>, ErrResourceManager> {
+		return self.resource_manager.read().map_err(|_| ErrResourceManager::ManagerLockPoisoned);
+	}
+
+	fn write(&self) -> Result<RwLockWriteGuard<'_, ResourceManager<C>>, ErrResourceManager> {
+		return self.resource_manager.write().map_err(|_| ErrResourceManager::ManagerLockPoisoned);
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+	use super::*;
+
+	use std::thread;
+	use std::time::{Duration, SystemTime};
+
+	use crate::resources::clock::MockClock;
+	use crate::resources::interface::{Endorsement, ForwardOutcome};
+	use crate::resources::resource_manager::{BucketerType, ManagerConfig};
+	use crate::resources::types::MilliSatoshi;
+
+	fn assert_send_sync<T: Send + Sync>() {}
+
+	#[test]
+	fn test_shared_resource_manager() {
+		assert_send_sync::<SharedResourceManager<Arc<MockClock>>>();
+
+		let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000)));
+		let resource_manager = ResourceManager::new(ManagerConfig::default(), clock.clone()).unwrap();
+		let shared_manager = Arc::new(SharedResourceManager::new(resource_manager));
+
+		// Each thread forwards over its own pair of channels.
+		let threads = 4;
+		for i in 0..threads * 2 {
+			assert_eq!(shared_manager.add_channel(ShortChannelId(i), ChannelInfo::new(100, MilliSatoshi(100_000_000))).is_ok(), true);
+		}
+
+		let handles: Vec<_> = (0..threads).map(|i| {
+			let shared_manager = shared_manager.clone();
+			let clock = clock.clone();
+			thread::spawn(move || {
+				let (incoming, outgoing) = (ShortChannelId(i * 2), ShortChannelId(i * 2 + 1));
+				for index in 0..20 {
+					let proposed_htlc = ProposedHTLC::new(incoming, outgoing, index, Endorsement::EndorsementFalse,
+						MilliSatoshi(10_100), MilliSatoshi(10_000), 40, 40);
+					let decision = shared_manager.forward_htlc(proposed_htlc).unwrap();
+					assert_eq!(decision.forward_outcome(), ForwardOutcome::ForwardOutcomeUnendorsed);
+
+					let resolved_htlc = ResolvedHTLC::new(clock.now(), incoming, index, outgoing, index, true);
+					assert_eq!(shared_manager.resolve_htlc(resolved_htlc).is_ok(), true);
+				}
+			})
+		}).collect();
+
+		for handle in handles {
+			handle.join().unwrap();
+		}
+
+		let snapshot = shared_manager.snapshot().unwrap();
+		assert_eq!(snapshot.channels.len(), (threads * 2) as usize);
+		for channel in snapshot.channels {
+			assert_eq!(channel.reputation.unwrap().in_flight_htlcs.len(), 0);
+		}
+	}
+
+	#[test]
+	fn test_shared_resource_manager_contended() {
+		for bucketer in [BucketerType::Locking, BucketerType::Atomic] {
+			let clock = Arc::new(MockClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000)));
+			let manager_config = ManagerConfig { bucketer, ..ManagerConfig::default() };
+			let shared_manager = Arc::new(SharedResourceManager::new(ResourceManager::new(manager_config, clock.clone()).unwrap()));
+
+			// Every thread forwards from its own incoming channel over the same outgoing channel, which has 50
+			// general slots.
+			let threads = 8;
+			let outgoing = ShortChannelId(threads);
+			assert_eq!(shared_manager.add_channel(outgoing, ChannelInfo::new(100, MilliSatoshi(100_000_000))).is_ok(), true);
+			for i in 0..threads {
+				assert_eq!(shared_manager.add_channel(ShortChannelId(i), ChannelInfo::new(100, MilliSatoshi(100_000_000))).is_ok(), true);
+			}
+
+			// Each thread holds a window of HTLCs, resolving the oldest before forwarding another.
+			let handles: Vec<_> = (0..threads).map(|i| {
+				let shared_manager = shared_manager.clone();
+				let clock = clock.clone();
+				thread::spawn(move || {
+					let incoming = ShortChannelId(i);
+					let mut in_flight = std::collections::VecDeque::new();
+					for index in 0..500 {
+						let proposed_htlc = ProposedHTLC::new(incoming, outgoing, index, Endorsement::EndorsementFalse,
+							MilliSatoshi(10_100), MilliSatoshi(10_000), 40, 40);
+						let decision = shared_manager.forward_htlc(proposed_htlc).unwrap();
+						if decision.bucket().is_some() {
+							in_flight.push_back(index);
+						} else {
+							assert_eq!(shared_manager.cancel_forward(incoming, index).is_ok(), true);
+						}
+
+						if in_flight.len() > 10 {
+							let index = in_flight.pop_front().unwrap();
+							let resolved_htlc = ResolvedHTLC::new(clock.now(), incoming, index, outgoing, index, true);
+							assert_eq!(shared_manager.resolve_htlc(resolved_htlc).is_ok(), true);
+						}
+					}
+
+					for index in in_flight {
+						let resolved_htlc = ResolvedHTLC::new(clock.now(), incoming, index, outgoing, index, true);
+						assert_eq!(shared_manager.resolve_htlc(resolved_htlc).is_ok(), true);
+					}
+				})
+			}).collect();
+
+			for handle in handles {
+				handle.join().unwrap();
+			}
+
+			// Once every HTLC is resolved, racing threads are given exactly as many slots as the bucket has.
+			let handles: Vec<_> = (0..threads).map(|i| {
+				let shared_manager = shared_manager.clone();
+				thread::spawn(move || {
+					return (1_000..1_020).filter(|index| {
+						let proposed_htlc = ProposedHTLC::new(ShortChannelId(i), outgoing, *index, Endorsement::EndorsementFalse,
+							MilliSatoshi(10_100), MilliSatoshi(10_000), 40, 40);
+						return shared_manager.forward_htlc(proposed_htlc).unwrap().bucket().is_some();
+					}).count();
+				})
+			}).collect();
+
+			let accepted: usize = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+			assert_eq!(accepted, 50, "{:?}", bucketer);
+		}
+	}
+}
@@ -1,18 +1,25 @@
 
 use std::time::Duration;
 use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard};
 
+use crate::resources::clock::Clock;
 use crate::resources::decaying_average::{DecayingAverage, DecayingAverageStart};
-use crate::resources::resource_manager::ManagerConfig;
-use crate::resources::interface::{ChannelInfo, Endorsement, ForwardDecision, ForwardOutcome, InFlightHTLC, IncomingReputation, ProposedHTLC, ReputationCheck, ResourceBucketer, ResolvedHTLC, TargetMonitor};
-use crate::resources::resource_bucketing::BucketResourceManager;
-use crate::resources::reputation_tracker::ReputationTracker;
-
-pub struct TargetChannelTracker<R: Deref>
+use crate::resources::error::ErrResourceManager;
+use crate::resources::resource_manager::{EndorsementPolicy, ManagerConfig};
+use crate::resources::interface::{Bucket, BucketUsage, HTLCClass, ForwardDecision, ForwardOutcome, InFlightHTLC, IncomingReputation, ProposedHTLC, ReputationCheck, ResourceBucketer, ResolvedHTLC, TargetMonitor};
+use crate::resources::reputation_tracker::outstanding_risk;
+use crate::resources::resource_bucketing::ErrBucketing;
+use crate::resources::types::MilliSatoshi;
+
+pub struct TargetChannelTracker<R: Deref, C: Deref>
 	where R::Target: ResourceBucketer,
+		C::Target: Clock,
 {
 
-	revenue: DecayingAverage,
+	/// The channel's revenue is locked separately from its buckets, so that bucketers that don't need a lock can
+	/// reserve resources for HTLCs without waiting on each other.
+	revenue: Mutex<DecayingAverage>,
 
 	/// Expected time to find a block, surfaced to account for simulation scenarios
 	/// where this isn't 10 minutes in average.
@@ -21,64 +28,164 @@ pub struct TargetChannelTracker<R: Deref>
 	/// The amount of time that we reasonably expect a HTLC to resolve in.
 	resolution_period: Duration,
 
+	/// The endorsement signal that is set on HTLCs forwarded over the channel.
+	endorsement_policy: EndorsementPolicy,
+
 	resource_buckets: R,
+
+	clock: C,
 }
 
-impl <R: Deref>TargetChannelTracker<R>
+impl <R: Deref, C: Deref>TargetChannelTracker<R, C>
 	where R::Target: ResourceBucketer,
+		C::Target: Clock,
 {
-	pub(crate) fn new(manager_config: ManagerConfig, chan_info: ChannelInfo, start_value: DecayingAverageStart, resource_buckets: R) -> Result<Self, ()> {
+	/// Creates a tracker whose revenue decays over the revenue window provided.
+	pub(crate) fn new(manager_config: ManagerConfig, revenue_window: Duration, start_value: DecayingAverageStart, resource_buckets: R, clock: C) -> Self {
 
-		let decaying_average = DecayingAverage::new(Duration::from_secs(0), start_value);
+		let decaying_average = DecayingAverage::new(revenue_window, start_value);
 
-		return Ok(TargetChannelTracker {
-			revenue: decaying_average,
-			resource_buckets: resource_buckets,
+		return TargetChannelTracker {
+			revenue: Mutex::new(decaying_average),
+			resource_buckets,
 			block_time: manager_config.block_time.as_secs() as f64,
 			resolution_period: manager_config.resolution_period,
-		});
+			endorsement_policy: manager_config.endorsement_policy,
+			clock,
+		};
 	}
-}
 
-impl <R: Deref>TargetMonitor for TargetChannelTracker<R>
-	where R::Target: ResourceBucketer,
-{
-	fn add_inflight(&mut self, incoming_reputation: IncomingReputation, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ()> {
-		
-		let reputation_check = ReputationCheck {
+	/// Returns the channel's revenue, decayed to the current time.
+	pub(crate) fn revenue_value(&self) -> f64 {
+		return self.lock_revenue().get_value(self.clock.now());
+	}
+
+	/// Returns the undecayed state of the tracker's revenue, for persistence.
+	pub(crate) fn revenue_snapshot(&self) -> DecayingAverageStart {
+		return self.lock_revenue().snapshot();
+	}
+
+	/// Locks the channel's revenue. A poisoned lock is recovered, as the revenue is always left in a valid state.
+	fn lock_revenue(&self) -> MutexGuard<'_, DecayingAverage> {
+		return self.revenue.lock().unwrap_or_else(|err| err.into_inner());
+	}
+
+	/// Returns the current usage of each of the channel's buckets.
+	pub(crate) fn bucket_occupancy(&self) -> Vec<BucketUsage> {
+		return self.resource_buckets.occupancy();
+	}
+
+	/// Updates the channel's buckets for new liquidity and slot limits, keeping any HTLCs that are in flight.
+	pub(crate) fn resize_buckets(&self, in_flight_liquidity_limit: MilliSatoshi, in_flight_htlc_limit: u64) -> Result<(), ErrBucketing> {
+		return self.resource_buckets.resize(in_flight_liquidity_limit, in_flight_htlc_limit);
+	}
+
+	/// Returns the decision that would be made for the HTLC provided, without reserving resources for it.
+	pub(crate) fn evaluate_htlc(&self, incoming_reputation: IncomingReputation, proposed_htlc: &ProposedHTLC) -> ForwardDecision {
+		let reputation_check = self.reputation_check(incoming_reputation, proposed_htlc);
+		let htlc_class = HTLCClass::new(&reputation_check, &proposed_htlc.incoming_endorsed);
+
+		let bucket = self.resource_buckets.available_bucket(proposed_htlc.incoming_channel, htlc_class,
+			proposed_htlc.outgoing_amount_msat);
+
+		return forward_decision(reputation_check, htlc_class, bucket, self.endorsement_policy, proposed_htlc);
+	}
+
+	fn reputation_check(&self, incoming_reputation: IncomingReputation, proposed_htlc: &ProposedHTLC) -> ReputationCheck {
+		return ReputationCheck {
 			incoming_reputation,
-			outgoing_revenue: self.revenue.get_value(),
-			htlc_risk: ReputationTracker::outstanding_risk(self.block_time, proposed_htlc.clone(), self.resolution_period),
+			outgoing_revenue: self.revenue_value(),
+			htlc_risk: outstanding_risk(self.block_time, proposed_htlc.fee_msat(), proposed_htlc.cltv_expiry_delta,
+				self.resolution_period),
 		};
+	}
 
-		let htlc_protected = reputation_check.sufficient_reputation() && proposed_htlc.incoming_endorsed == Endorsement::EndorsementTrue;
+	/// Releases the resources of a HTLC that was never added to the outgoing channel, without counting its fees
+	/// towards the channel's revenue.
+	pub(crate) fn cancel_inflight(&self, in_flight_htlc: &InFlightHTLC) -> Result<bool, ErrResourceManager> {
+		return match in_flight_htlc.bucket {
+			Some(bucket) => self.release_inflight(bucket, in_flight_htlc),
+			None => Ok(true),
+		};
+	}
 
-		let can_forward = self.resource_buckets.add_htlc(htlc_protected, proposed_htlc.outgoing_amount_msat);
+	fn release_inflight(&self, bucket: Bucket, in_flight_htlc: &InFlightHTLC) -> Result<bool, ErrResourceManager> {
+		let outgoing_channel = in_flight_htlc.proposed_htlc.outgoing_channel;
 
-		let outcome = if !can_forward { ForwardOutcome::ForwardOutcomeNoResources }
-		else if htlc_protected { ForwardOutcome::ForwardOutcomeEndorsed }
-		else { ForwardOutcome::ForwardOutcomeUnendorsed };
+		self.resource_buckets.remove_htlc(in_flight_htlc.proposed_htlc.incoming_channel, bucket,
+			in_flight_htlc.proposed_htlc.outgoing_amount_msat)
+			.map_err(|err| ErrResourceManager::Bucketing { outgoing_channel, err })?;
 
-		return Ok(ForwardDecision {
-			reputation_check,
-			forward_outcome: outcome,
-		});
+		return Ok(true);
+	}
+
+	/// Reserves resources for a HTLC that is already in flight, returning the bucket it was added to if there
+	/// was space for it.
+	pub(crate) fn reserve_inflight(&self, htlc_class: HTLCClass, proposed_htlc: &ProposedHTLC) -> Option<Bucket> {
+		return self.resource_buckets.add_htlc(proposed_htlc.incoming_channel, htlc_class, proposed_htlc.outgoing_amount_msat);
+	}
+
+	/// Reoccupies the resources of a HTLC that was in flight when the tracker's state was persisted, even if the
+	/// channel has since been shrunk. Returns false if the HTLC's bucket no longer exists.
+	pub(crate) fn restore_inflight(&self, in_flight_htlc: &InFlightHTLC) -> bool {
+		return match in_flight_htlc.bucket {
+			Some(bucket) => self.resource_buckets.restore_htlc(in_flight_htlc.proposed_htlc.incoming_channel, bucket,
+				in_flight_htlc.proposed_htlc.outgoing_amount_msat),
+			None => true,
+		};
 	}
+}
+
+/// Returns the decision for a HTLC of the class provided that was assigned the bucket provided, if any.
+fn forward_decision(reputation_check: ReputationCheck, htlc_class: HTLCClass, bucket: Option<Bucket>,
+	endorsement_policy: EndorsementPolicy, proposed_htlc: &ProposedHTLC) -> ForwardDecision {
+	// Protected HTLCs that overflow into another bucket are still forwarded as endorsed.
+	let outcome = if bucket.is_none() { ForwardOutcome::ForwardOutcomeNoResources }
+	else if htlc_class == HTLCClass::EndorsedSufficientReputation { ForwardOutcome::ForwardOutcomeEndorsed }
+	else { ForwardOutcome::ForwardOutcomeUnendorsed };
+
+	return ForwardDecision {
+		reputation_check,
+		outgoing_endorsement: endorsement_policy.outgoing_endorsement(&proposed_htlc.incoming_endorsed, &outcome),
+		forward_outcome: outcome,
+		bucket,
+	};
+}
 
-	fn resolve_inflight(&mut self, resolved_htlc: ResolvedHTLC, in_flight_htlc: InFlightHTLC) -> Result<bool, ()> {
+impl <R: Deref, C: Deref>TargetMonitor for TargetChannelTracker<R, C>
+	where R::Target: ResourceBucketer,
+		C::Target: Clock,
+{
+	fn add_inflight(&self, incoming_reputation: IncomingReputation, proposed_htlc: ProposedHTLC) -> Result<ForwardDecision, ErrResourceManager> {
 		
-		if in_flight_htlc.outgoing_decision == ForwardOutcome::ForwardOutcomeNoResources {
-			return Err(());
-		}
+		let reputation_check = self.reputation_check(incoming_reputation, &proposed_htlc);
+		let htlc_class = HTLCClass::new(&reputation_check, &proposed_htlc.incoming_endorsed);
+
+		let bucket = self.resource_buckets.add_htlc(proposed_htlc.incoming_channel, htlc_class,
+			proposed_htlc.outgoing_amount_msat);
+
+		return Ok(forward_decision(reputation_check, htlc_class, bucket, self.endorsement_policy, &proposed_htlc));
+	}
+
+	fn resolve_inflight(&self, resolved_htlc: ResolvedHTLC, in_flight_htlc: InFlightHTLC) -> Result<bool, ErrResourceManager> {
+		
+		let bucket = match in_flight_htlc.bucket {
+			Some(bucket) => bucket,
+			None => return Err(ErrResourceManager::NoResourcesAssigned {
+				incoming_channel: in_flight_htlc.proposed_htlc.incoming_channel,
+				incoming_index: in_flight_htlc.proposed_htlc.incoming_index,
+			}),
+		};
+
+		// The HTLC's resources are released first, so that they are not left occupied if its fees can't be
+		// credited, for example because the clock has stepped backwards.
+		self.release_inflight(bucket, &in_flight_htlc)?;
 
 		if resolved_htlc.success {
-			self.revenue.add(in_flight_htlc.proposed_htlc.forwarding_fee() as f64);
+			self.lock_revenue().add(in_flight_htlc.proposed_htlc.fee_msat() as f64, self.clock.now())
+				.map_err(|err| ErrResourceManager::DecayingAverage { short_channel_id: in_flight_htlc.proposed_htlc.outgoing_channel, err })?;
 		}
 
-		//TODO: is that a bug ?
-		self.resource_buckets.remove_htlc(in_flight_htlc.outgoing_decision == ForwardOutcome::ForwardOutcomeEndorsed,
-			in_flight_htlc.proposed_htlc.outgoing_amount_msat);
-
 		return Ok(true);
 	}
 }
@@ -87,23 +194,38 @@ impl <R: Deref>TargetMonitor for TargetChannelTracker<R>
 mod tests {
 	use super::*;
 
-	use std::time::Instant;
+	use std::time::SystemTime;
+	use crate::resources::clock::MockClock;
+	use crate::resources::interface::{ChannelInfo, Endorsement};
+	use crate::resources::resource_bucketing::BucketResourceManager;
+	use crate::resources::types::{MilliSatoshi, ShortChannelId};
 
 	#[test]
 	fn test_target_channel_tracker() {
 		let manager_config = ManagerConfig::default();
-		let chan_info = ChannelInfo {
-			in_flight_htlc_limit: 200,
-			in_flight_liquidity_limit: 100_000,
-		};
+		let chan_info = ChannelInfo::new(200, MilliSatoshi(100_000));
 
-		let bucket_resource_manager = BucketResourceManager::new(chan_info.in_flight_liquidity_limit, chan_info.in_flight_htlc_limit, manager_config.protected_percentage);
+		let bucket_resource_manager = BucketResourceManager::new(chan_info.in_flight_liquidity_limit, chan_info.in_flight_htlc_limit, manager_config.protected_percentage).unwrap();
 
+		let mock_clock = MockClock::new(SystemTime::UNIX_EPOCH);
 		let decaying_average_start = DecayingAverageStart {
-			last_update: Instant::now(),
+			last_update: SystemTime::UNIX_EPOCH,
 			value: 0.0,
 		};
 
-		let target_channel_tracker = TargetChannelTracker::new(manager_config, chan_info, decaying_average_start, &mut bucket_resource_manager.unwrap());
+		let target_channel_tracker = TargetChannelTracker::new(manager_config, manager_config.revenue_window, decaying_average_start, &bucket_resource_manager, &mock_clock);
+
+		let in_flight_htlc = InFlightHTLC {
+			timestamp_added: SystemTime::UNIX_EPOCH,
+			outgoing_decision: ForwardOutcome::ForwardOutcomeNoResources,
+			bucket: None,
+			expiry_height: Some(40),
+			outgoing_index: None,
+			proposed_htlc: ProposedHTLC::new(ShortChannelId(1), ShortChannelId(2), 3, Endorsement::EndorsementNone, MilliSatoshi(1_100), MilliSatoshi(1_000), 40, 40),
+		};
+		let resolved_htlc = ResolvedHTLC::new(SystemTime::UNIX_EPOCH, ShortChannelId(1), 3, ShortChannelId(2), 0, false);
+
+		assert_eq!(target_channel_tracker.resolve_inflight(resolved_htlc, in_flight_htlc).err(),
+			Some(ErrResourceManager::NoResourcesAssigned { incoming_channel: ShortChannelId(1), incoming_index: 3 }));
 	}
 }
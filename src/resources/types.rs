@@ -0,0 +1,167 @@
+use core::fmt;
+use core::num::{IntErrorKind, ParseIntError};
+use core::str::FromStr;
+
+/// The largest block height or transaction index that can be encoded in a short channel id.
+const MAX_SCID_BLOCK_TX: u32 = 0xFF_FFFF;
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ErrShortChannelId {
+	/// The string is not in block x transaction x output format.
+	InvalidFormat,
+	/// The block height, transaction index or output index is too large to be encoded.
+	OutOfRange,
+}
+
+impl fmt::Display for ErrShortChannelId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ErrShortChannelId::InvalidFormat => write!(f, "short channel id must be formatted as block x tx x output"),
+			ErrShortChannelId::OutOfRange => write!(f, "short channel id component out of range"),
+		}
+	}
+}
+
+impl std::error::Error for ErrShortChannelId {}
+
+/// This identifies a channel by the position of its funding output in the chain, as defined in BOLT 7. The
+/// block height is encoded in the top 3 bytes, the transaction index in the next 3 bytes and the output index
+/// in the last 2 bytes. Channels that use SCID aliases are identified by their alias in the same way.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShortChannelId(pub u64);
+
+impl ShortChannelId {
+	/// Creates a short channel id from its components, returning None if they can't be encoded.
+	pub fn new(block_height: u32, tx_index: u32, output_index: u16) -> Option<Self> {
+		if block_height > MAX_SCID_BLOCK_TX || tx_index > MAX_SCID_BLOCK_TX {
+			return None;
+		}
+
+		return Some(ShortChannelId((block_height as u64) << 40 | (tx_index as u64) << 16 | output_index as u64));
+	}
+
+	/// Returns the height of the block that the channel's funding transaction was confirmed in.
+	pub fn block_height(&self) -> u32 {
+		return (self.0 >> 40) as u32;
+	}
+
+	/// Returns the index of the channel's funding transaction in its block.
+	pub fn tx_index(&self) -> u32 {
+		return ((self.0 >> 16) & MAX_SCID_BLOCK_TX as u64) as u32;
+	}
+
+	/// Returns the index of the channel's funding output in its transaction.
+	pub fn output_index(&self) -> u16 {
+		return self.0 as u16;
+	}
+}
+
+impl fmt::Display for ShortChannelId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}x{}x{}", self.block_height(), self.tx_index(), self.output_index())
+	}
+}
+
+impl FromStr for ShortChannelId {
+	type Err = ErrShortChannelId;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = s.split('x');
+		let (block_height, tx_index, output_index) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+			(Some(block_height), Some(tx_index), Some(output_index), None) => (block_height, tx_index, output_index),
+			_ => return Err(ErrShortChannelId::InvalidFormat),
+		};
+
+		let block_height = block_height.parse::<u32>().map_err(part_error)?;
+		let tx_index = tx_index.parse::<u32>().map_err(part_error)?;
+		let output_index = output_index.parse::<u16>().map_err(part_error)?;
+
+		return ShortChannelId::new(block_height, tx_index, output_index).ok_or(ErrShortChannelId::OutOfRange);
+	}
+}
+
+/// Returns the error for a part of a short channel id that could not be parsed. Numbers that are too large for
+/// their part are out of range, and anything else is not a valid short channel id.
+fn part_error(err: ParseIntError) -> ErrShortChannelId {
+	return match err.kind() {
+		IntErrorKind::PosOverflow => ErrShortChannelId::OutOfRange,
+		_ => ErrShortChannelId::InvalidFormat,
+	};
+}
+
+/// This is an amount of bitcoin in thousandths of a satoshi. Arithmetic on amounts is checked, so that
+/// amounts never overflow or underflow.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MilliSatoshi(pub u64);
+
+impl MilliSatoshi {
+	pub const ZERO: MilliSatoshi = MilliSatoshi(0);
+
+	/// Returns the amount in millisatoshis.
+	pub fn to_msat(&self) -> u64 {
+		return self.0;
+	}
+
+	/// Returns the sum of two amounts, or None if it overflows.
+	pub fn checked_add(self, other: MilliSatoshi) -> Option<MilliSatoshi> {
+		return self.0.checked_add(other.0).map(MilliSatoshi);
+	}
+
+	/// Returns the difference between two amounts, or None if the amount subtracted is larger.
+	pub fn checked_sub(self, other: MilliSatoshi) -> Option<MilliSatoshi> {
+		return self.0.checked_sub(other.0).map(MilliSatoshi);
+	}
+}
+
+impl fmt::Display for MilliSatoshi {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} msat", self.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_short_channel_id() {
+		let short_channel_id = ShortChannelId::from_str("800000x1x0").unwrap();
+		assert_eq!(short_channel_id, ShortChannelId::new(800_000, 1, 0).unwrap());
+		assert_eq!(short_channel_id.0, 879_609_302_220_800_000 + (1 << 16));
+		assert_eq!(short_channel_id.block_height(), 800_000);
+		assert_eq!(short_channel_id.tx_index(), 1);
+		assert_eq!(short_channel_id.output_index(), 0);
+		assert_eq!(short_channel_id.to_string(), "800000x1x0");
+
+		assert_eq!(ShortChannelId::from_str("800000x1").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000x1x0x0").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000xax0").err(), Some(ErrShortChannelId::InvalidFormat));
+
+		// Each part is invalid if it isn't a number, and out of range if it's too large.
+		assert_eq!(ShortChannelId::from_str("ax1x0").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("-1x1x0").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("x1x0").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("16777216x1x0").err(), Some(ErrShortChannelId::OutOfRange));
+		assert_eq!(ShortChannelId::from_str("4294967296x1x0").err(), Some(ErrShortChannelId::OutOfRange));
+
+		assert_eq!(ShortChannelId::from_str("800000x-1x0").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000xx0").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000x16777216x0").err(), Some(ErrShortChannelId::OutOfRange));
+		assert_eq!(ShortChannelId::from_str("800000x4294967296x0").err(), Some(ErrShortChannelId::OutOfRange));
+
+		assert_eq!(ShortChannelId::from_str("800000x1xa").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000x1x-1").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000x1x").err(), Some(ErrShortChannelId::InvalidFormat));
+		assert_eq!(ShortChannelId::from_str("800000x1x65536").err(), Some(ErrShortChannelId::OutOfRange));
+	}
+
+	#[test]
+	fn test_milli_satoshi() {
+		assert_eq!(MilliSatoshi(1_100).checked_sub(MilliSatoshi(1_000)), Some(MilliSatoshi(100)));
+		assert_eq!(MilliSatoshi(1_000).checked_sub(MilliSatoshi(1_100)), None);
+		assert_eq!(MilliSatoshi(u64::MAX).checked_add(MilliSatoshi(1)), None);
+		assert_eq!(MilliSatoshi(1_000).to_string(), "1000 msat");
+	}
+}
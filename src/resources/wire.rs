@@ -0,0 +1,309 @@
+use core::fmt;
+
+use crate::resources::interface::{Endorsement, ForwardDecision, ForwardOutcome};
+
+/// The `update_add_htlc` TLV type of the blinding point used for route blinding, as defined in BOLT 2.
+pub const BLINDING_POINT_TLV_TYPE: u64 = 0;
+
+/// The experimental `update_add_htlc` TLV type that carries a HTLC's endorsement signal.
+pub const ENDORSEMENT_TLV_TYPE: u64 = 106823;
+
+/// The even `update_add_htlc` TLV types that are understood, so that streams containing them are accepted.
+const UPDATE_ADD_HTLC_EVEN_TYPES: [u64; 1] = [BLINDING_POINT_TLV_TYPE];
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ErrWire {
+	/// The stream ended part way through a value.
+	ShortRead,
+	/// A BigSize value was not encoded in the fewest bytes possible.
+	NonCanonicalBigSize,
+	/// A record's type was not greater than the type of the record before it.
+	UnorderedRecords,
+	/// The stream contains an even record type that isn't understood.
+	UnknownEvenType(u64),
+	/// The endorsement record's value is not a single byte.
+	InvalidEndorsement,
+}
+
+impl fmt::Display for ErrWire {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ErrWire::ShortRead => write!(f, "unexpected end of tlv stream"),
+			ErrWire::NonCanonicalBigSize => write!(f, "bigsize not minimally encoded"),
+			ErrWire::UnorderedRecords => write!(f, "tlv records not strictly increasing"),
+			ErrWire::UnknownEvenType(record_type) => write!(f, "unknown even tlv type {}", record_type),
+			ErrWire::InvalidEndorsement => write!(f, "endorsement tlv must be a single byte"),
+		}
+	}
+}
+
+impl std::error::Error for ErrWire {}
+
+/// This is a single record read from a TLV stream.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TlvRecord<'a> {
+	pub record_type: u64,
+	pub value: &'a [u8],
+}
+
+/// Reads a BigSize value from the front of the buffer provided, advancing it past the value.
+pub fn read_bigsize(buf: &mut &[u8]) -> Result<u64, ErrWire> {
+	let (prefix, rest) = buf.split_first().ok_or(ErrWire::ShortRead)?;
+	let (len, min) = match prefix {
+		0xfd => (2, 0xfd),
+		0xfe => (4, 0x1_0000),
+		0xff => (8, 0x1_0000_0000),
+		_ => {
+			*buf = rest;
+			return Ok(*prefix as u64);
+		},
+	};
+
+	if rest.len() < len {
+		return Err(ErrWire::ShortRead);
+	}
+
+	let (bytes, rest) = rest.split_at(len);
+	let value = bytes.iter().fold(0u64, |value, byte| value << 8 | *byte as u64);
+	if value < min {
+		return Err(ErrWire::NonCanonicalBigSize);
+	}
+
+	*buf = rest;
+	return Ok(value);
+}
+
+/// Appends the minimal BigSize encoding of a value to the writer.
+pub fn write_bigsize(value: u64, writer: &mut Vec<u8>) {
+	match value {
+		0..=0xfc => writer.push(value as u8),
+		0xfd..=0xffff => {
+			writer.push(0xfd);
+			writer.extend_from_slice(&(value as u16).to_be_bytes());
+		},
+		0x1_0000..=0xffff_ffff => {
+			writer.push(0xfe);
+			writer.extend_from_slice(&(value as u32).to_be_bytes());
+		},
+		_ => {
+			writer.push(0xff);
+			writer.extend_from_slice(&value.to_be_bytes());
+		},
+	}
+}
+
+/// Reads every record in a TLV stream, checking that the stream is well formed and that its records are in
+/// strictly increasing order of type. Records are returned whether or not their type is understood.
+pub fn read_tlv_stream(mut stream: &[u8]) -> Result<Vec<TlvRecord<'_>>, ErrWire> {
+	let mut records: Vec<TlvRecord> = Vec::new();
+
+	while !stream.is_empty() {
+		let record_type = read_bigsize(&mut stream)?;
+		let length = read_bigsize(&mut stream)?;
+		if length > stream.len() as u64 {
+			return Err(ErrWire::ShortRead);
+		}
+
+		if records.last().is_some_and(|last| last.record_type >= record_type) {
+			return Err(ErrWire::UnorderedRecords);
+		}
+
+		let (value, rest) = stream.split_at(length as usize);
+		records.push(TlvRecord { record_type, value });
+		stream = rest;
+	}
+
+	return Ok(records);
+}
+
+/// Appends a single TLV record to the writer. Records must be written in increasing order of type.
+pub fn write_tlv_record(record_type: u64, value: &[u8], writer: &mut Vec<u8>) {
+	write_bigsize(record_type, writer);
+	write_bigsize(value.len() as u64, writer);
+	writer.extend_from_slice(value);
+}
+
+/// Reads the endorsement signal from the TLV extension of an incoming `update_add_htlc`. The stream is rejected
+/// if it is malformed, or if it contains an even type that isn't understood.
+pub fn read_endorsement(stream: &[u8]) -> Result<Endorsement, ErrWire> {
+	let mut endorsement = Endorsement::EndorsementNone;
+
+	for record in read_tlv_stream(stream)? {
+		if record.record_type == ENDORSEMENT_TLV_TYPE {
+			endorsement = match record.value {
+				[endorsed] => Endorsement::new_endorsement_signal(*endorsed != 0),
+				_ => return Err(ErrWire::InvalidEndorsement),
+			};
+		} else if record.record_type % 2 == 0 && !UPDATE_ADD_HTLC_EVEN_TYPES.contains(&record.record_type) {
+			return Err(ErrWire::UnknownEvenType(record.record_type));
+		}
+	}
+
+	return Ok(endorsement);
+}
+
+/// Appends the endorsement record for an outgoing `update_add_htlc` to the writer. Nothing is written if the
+/// signal is absent.
+pub fn write_endorsement(endorsement: &Endorsement, writer: &mut Vec<u8>) {
+	let endorsed = match endorsement {
+		Endorsement::EndorsementNone => return,
+		Endorsement::EndorsementFalse => 0,
+		Endorsement::EndorsementTrue => 1,
+	};
+
+	write_tlv_record(ENDORSEMENT_TLV_TYPE, &[endorsed], writer);
+}
+
+/// Returns the endorsement record to set on the outgoing `update_add_htlc` for a forwarding decision, or None if
+/// the HTLC should not be forwarded. The record carries the signal chosen by the manager's endorsement policy.
+pub fn outgoing_endorsement_tlv(forward_decision: &ForwardDecision) -> Option<Vec<u8>> {
+	if forward_decision.forward_outcome == ForwardOutcome::ForwardOutcomeNoResources {
+		return None;
+	}
+
+	let mut writer = Vec::new();
+	write_endorsement(&forward_decision.outgoing_endorsement, &mut writer);
+	return Some(writer);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::resources::interface::{IncomingReputation, ReputationCheck};
+	use crate::resources::resource_manager::EndorsementPolicy;
+
+	fn from_hex(hex: &str) -> Vec<u8> {
+		return (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+	}
+
+	#[test]
+	fn test_bigsize() {
+		// Test vectors from BOLT 1, appendix A.
+		let vectors: [(u64, &str); 8] = [
+			(0, "00"),
+			(252, "fc"),
+			(253, "fd00fd"),
+			(65535, "fdffff"),
+			(65536, "fe00010000"),
+			(4294967295, "feffffffff"),
+			(4294967296, "ff0000000100000000"),
+			(18446744073709551615, "ffffffffffffffffff"),
+		];
+		for (value, hex) in vectors {
+			let encoded = from_hex(hex);
+			assert_eq!(read_bigsize(&mut &encoded[..]), Ok(value));
+
+			let mut writer = Vec::new();
+			write_bigsize(value, &mut writer);
+			assert_eq!(writer, encoded);
+		}
+
+		let failures: [(&str, ErrWire); 8] = [
+			("fd00fc", ErrWire::NonCanonicalBigSize),
+			("fe0000ffff", ErrWire::NonCanonicalBigSize),
+			("ff00000000ffffffff", ErrWire::NonCanonicalBigSize),
+			("fd00", ErrWire::ShortRead),
+			("feffff", ErrWire::ShortRead),
+			("ffffffffff", ErrWire::ShortRead),
+			("", ErrWire::ShortRead),
+			("fd", ErrWire::ShortRead),
+		];
+		for (hex, err) in failures {
+			assert_eq!(read_bigsize(&mut &from_hex(hex)[..]), Err(err), "{}", hex);
+		}
+	}
+
+	#[test]
+	fn test_read_tlv_stream() {
+		// Decoding failures from BOLT 1, appendix B, that apply to any namespace.
+		let failures: [(&str, ErrWire); 12] = [
+			("fd", ErrWire::ShortRead),
+			("fd01", ErrWire::ShortRead),
+			("fd000100", ErrWire::NonCanonicalBigSize),
+			("fd0101", ErrWire::ShortRead),
+			("0ffd", ErrWire::ShortRead),
+			("0ffd26", ErrWire::ShortRead),
+			("0ffd2602", ErrWire::ShortRead),
+			("0ffd000100", ErrWire::NonCanonicalBigSize),
+			("1200", ErrWire::UnknownEvenType(0x12)),
+			("fd010200", ErrWire::UnknownEvenType(0x0102)),
+			("fe0100000200", ErrWire::UnknownEvenType(0x01000002)),
+			("ff010000000000000200", ErrWire::UnknownEvenType(0x0100000000000002)),
+		];
+		for (hex, err) in failures {
+			assert_eq!(read_endorsement(&from_hex(hex)), Err(err), "{}", hex);
+		}
+		assert_eq!(read_endorsement(&from_hex(&format!("0ffd0201{}", "00".repeat(512)))), Err(ErrWire::ShortRead));
+
+		// Unknown odd types are ignored.
+		for hex in ["", "2100", "fd020100", "fd00fd00", "fd00ff00", "fe0200000100", "ff020000000000000100"] {
+			assert_eq!(read_endorsement(&from_hex(hex)), Ok(Endorsement::EndorsementNone), "{}", hex);
+		}
+
+		// Records must be strictly increasing, even if they are understood.
+		assert_eq!(read_tlv_stream(&from_hex("21002100")), Err(ErrWire::UnorderedRecords));
+		assert_eq!(read_tlv_stream(&from_hex("2301212100")), Err(ErrWire::UnorderedRecords));
+		assert_eq!(read_tlv_stream(&from_hex("0100230121")).unwrap(), vec![
+			TlvRecord { record_type: 1, value: &[] },
+			TlvRecord { record_type: 0x23, value: &[0x21] },
+		]);
+	}
+
+	#[test]
+	fn test_endorsement_tlv() {
+		for endorsement in [Endorsement::EndorsementNone, Endorsement::EndorsementFalse, Endorsement::EndorsementTrue] {
+			// The endorsement record follows the blinding point, which is understood.
+			let mut stream = Vec::new();
+			write_tlv_record(BLINDING_POINT_TLV_TYPE, &[2; 33], &mut stream);
+			write_endorsement(&endorsement, &mut stream);
+			assert_eq!(read_endorsement(&stream), Ok(endorsement));
+		}
+
+		// Any non-zero value is an endorsement.
+		assert_eq!(read_endorsement(&from_hex("fe0001a14701ff")), Ok(Endorsement::EndorsementTrue));
+		assert_eq!(read_endorsement(&from_hex("fe0001a14700")), Err(ErrWire::InvalidEndorsement));
+		assert_eq!(read_endorsement(&from_hex("fe0001a147020001")), Err(ErrWire::InvalidEndorsement));
+		assert_eq!(read_endorsement(&from_hex("fe0001a14701")), Err(ErrWire::ShortRead));
+	}
+
+	fn forward_decision(endorsement_policy: EndorsementPolicy, incoming_endorsed: Endorsement, forward_outcome: ForwardOutcome) -> ForwardDecision {
+		return ForwardDecision {
+			reputation_check: ReputationCheck {
+				incoming_reputation: IncomingReputation { incoming_revenue: 0.0, in_flight_risk: 0.0 },
+				outgoing_revenue: 0.0,
+				htlc_risk: 0.0,
+			},
+			outgoing_endorsement: endorsement_policy.outgoing_endorsement(&incoming_endorsed, &forward_outcome),
+			forward_outcome,
+			bucket: None,
+		};
+	}
+
+	#[test]
+	fn test_outgoing_endorsement_tlv() {
+		let endorsed = from_hex("fe0001a1470101");
+		let unendorsed = from_hex("fe0001a1470100");
+		let tlv = |policy, incoming_endorsed, forward_outcome| outgoing_endorsement_tlv(&forward_decision(policy, incoming_endorsed, forward_outcome));
+
+		// HTLCs that won't be forwarded have no outgoing record, whatever the policy.
+		for policy in [EndorsementPolicy::RelayAsReceived, EndorsementPolicy::EndorseIfProtected, EndorsementPolicy::NeverEndorse] {
+			assert_eq!(tlv(policy, Endorsement::EndorsementTrue, ForwardOutcome::ForwardOutcomeNoResources), None);
+		}
+
+		// The incoming signal is relayed, including its absence, regardless of whether the HTLC was protected.
+		let policy = EndorsementPolicy::RelayAsReceived;
+		assert_eq!(tlv(policy, Endorsement::EndorsementTrue, ForwardOutcome::ForwardOutcomeUnendorsed), Some(endorsed.clone()));
+		assert_eq!(tlv(policy, Endorsement::EndorsementFalse, ForwardOutcome::ForwardOutcomeUnendorsed), Some(unendorsed.clone()));
+		assert_eq!(tlv(policy, Endorsement::EndorsementNone, ForwardOutcome::ForwardOutcomeUnendorsed), Some(Vec::new()));
+
+		// Only protected HTLCs are endorsed.
+		let policy = EndorsementPolicy::EndorseIfProtected;
+		assert_eq!(tlv(policy, Endorsement::EndorsementTrue, ForwardOutcome::ForwardOutcomeEndorsed), Some(endorsed.clone()));
+		assert_eq!(tlv(policy, Endorsement::EndorsementTrue, ForwardOutcome::ForwardOutcomeUnendorsed), Some(unendorsed.clone()));
+
+		// Nothing is endorsed, even if it was protected.
+		let policy = EndorsementPolicy::NeverEndorse;
+		assert_eq!(tlv(policy, Endorsement::EndorsementTrue, ForwardOutcome::ForwardOutcomeEndorsed), Some(unendorsed.clone()));
+		assert_eq!(tlv(policy, Endorsement::EndorsementTrue, ForwardOutcome::ForwardOutcomeUnendorsed), Some(unendorsed));
+	}
+}